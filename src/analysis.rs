@@ -0,0 +1,160 @@
+use crate::plot::{PlotData, Entry, SeriesKey};
+use crate::data::{Data, Profile, RebuildType, Timing};
+use crate::commit_id::CommitId;
+use crate::stats;
+use std::time::Duration;
+
+/// The slowdown reported as a regression when not otherwise specified
+pub const DEFAULT_THRESHOLD: f64 = 0.05;
+
+/// A slowdown between two consecutively plotted commits
+#[derive(Debug, Clone)]
+pub struct Regression {
+    pub key: SeriesKey,
+    pub before: Entry,
+    pub after: Entry,
+}
+
+impl Regression {
+    /// Relative change in mean duration, e.g. 0.1 for 10% slower
+    pub fn change(&self) -> f64 {
+        let before = self.before.duration.as_secs_f64();
+        let after = self.after.duration.as_secs_f64();
+        (after - before) / before
+    }
+}
+
+/// Finds steps that are slower by more than `threshold` (a fraction, e.g.
+/// 0.05) and by more than the combined standard deviation of both sides.
+/// Sorted biggest first.
+pub fn find_regressions(data: &PlotData, threshold: f64) -> Vec<Regression> {
+    let mut regressions = vec![];
+
+    for series in &data.series {
+        for pair in series.values.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            if before.duration == Duration::default() {
+                continue;
+            }
+            let regression = Regression {
+                key: series.key.clone(),
+                before: before.clone(),
+                after: after.clone(),
+            };
+            let noise = before.stddev() + after.stddev();
+            let diff = after.duration.checked_sub(before.duration).unwrap_or_default();
+            if regression.change() > threshold && diff > noise {
+                regressions.push(regression);
+            }
+        }
+    }
+
+    regressions.sort_by(|a, b| b.change().partial_cmp(&a.change()).expect("finite change"));
+    regressions
+}
+
+/// One configuration's timings at two commits
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub key: SeriesKey,
+    pub before: Vec<Duration>,
+    pub after: Vec<Duration>,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Verdict {
+    Slower,
+    Faster,
+    /// The difference is within the noise
+    NoChange,
+    /// Fewer than two samples on a side
+    TooFewSamples,
+}
+
+impl Comparison {
+    pub fn before_median(&self) -> Duration {
+        stats::median(&self.before)
+    }
+
+    pub fn after_median(&self) -> Duration {
+        stats::median(&self.after)
+    }
+
+    /// Difference of medians in seconds, positive when `after` is slower
+    pub fn diff(&self) -> f64 {
+        self.after_median().as_secs_f64() - self.before_median().as_secs_f64()
+    }
+
+    /// Relative difference of medians, e.g. 0.1 for 10% slower
+    pub fn change(&self) -> f64 {
+        self.diff() / self.before_median().as_secs_f64()
+    }
+
+    /// Whether the means differ by Welch's t-test at 95% confidence
+    pub fn verdict(&self) -> Verdict {
+        match stats::welch_t(&self.before, &self.after) {
+            None => Verdict::TooFewSamples,
+            Some((t, df)) if t.abs() > stats::t_critical_95(df) => {
+                if t > 0.0 { Verdict::Slower } else { Verdict::Faster }
+            }
+            Some(_) => Verdict::NoChange,
+        }
+    }
+}
+
+/// Compares every configuration with timings at either commit
+pub fn compare(data: &Data, before: &CommitId, after: &CommitId) -> Vec<Comparison> {
+    data.series_keys().into_iter().map(|key| Comparison {
+        before: data.samples(before, &key),
+        after: data.samples(after, &key),
+        key,
+    }).filter(|c| !c.before.is_empty() || !c.after.is_empty()).collect()
+}
+
+/// A configuration that failed to build at a commit
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub commit: CommitId,
+    pub key: SeriesKey,
+    pub failures: usize,
+    pub builds: usize,
+    /// The most recent failed build
+    pub latest: Timing,
+}
+
+/// Every configuration with a failed build, oldest commit first
+pub fn failures(data: &Data) -> Vec<Failure> {
+    let mut failures = vec![];
+    let keys = data.series_keys();
+    for commit in data.sorted_commits() {
+        let timings = match data.timings.get(&commit) {
+            Some(timings) => timings,
+            None => continue,
+        };
+        for key in &keys {
+            let builds: Vec<_> = timings.iter().filter(|t| t.is_in(key)).collect();
+            let failed: Vec<_> = builds.iter().filter(|t| t.result.failed()).collect();
+            if let Some(latest) = failed.iter().max_by_key(|t| t.start) {
+                failures.push(Failure {
+                    commit: commit.clone(),
+                    key: key.clone(),
+                    failures: failed.len(),
+                    builds: builds.len(),
+                    latest: (**latest).clone(),
+                });
+            }
+        }
+    }
+    failures
+}
+
+impl AsRef<str> for Verdict {
+    fn as_ref(&self) -> &str {
+        match self {
+            Verdict::Slower => "slower",
+            Verdict::Faster => "faster",
+            Verdict::NoChange => "no significant change",
+            Verdict::TooFewSamples => "too few samples",
+        }
+    }
+}
@@ -1,10 +1,9 @@
-use crate::data::BuildResult;
 use std::str::FromStr;
 use crate::cargo;
-use std::cmp::{self, Ordering};
-use crate::opts::GlobalOptions;
+use std::cmp;
+use crate::opts::{GlobalOptions, Toolchain};
 use std::error::Error as StdError;
-use crate::gnuplot::{PlotData, Entry};
+use crate::plot::{PlotData, Entry, SeriesKey};
 use crate::data::{Profile, RebuildType};
 use std::mem;
 use std::time::Duration;
@@ -12,38 +11,65 @@ use crate::git;
 use std::path::Path;
 use std::num;
 use crate::commit_id::CommitId;
+use crate::config::NamedConfig;
+use crate::data::BuildConfig;
+use std::collections::BTreeMap;
 
+/// The biggest change in a series between two plotted points
 #[derive(Debug, Clone)]
-struct BisectRange {
-    profile: Profile,
-    rebuild_type: RebuildType,
-    first: Entry,
-    last: Entry,
-    diff: Duration,
+pub struct BisectRange {
+    pub key: SeriesKey,
+    pub first: Entry,
+    pub last: Entry,
+    pub diff: Duration,
 }
 
-pub fn bisect(opts: &GlobalOptions, data: PlotData) -> Result<(), Error> {
-    let range = find_biggest_range(opts, data)?;
-    println!("bisecting {:#?}", range);
-    bisect_range(opts, range)
+impl BisectRange {
+    /// Whether a duration is on the side of `last`, beyond a tenth of
+    /// the difference from the midpoint
+    pub fn is_new(&self, d: Duration) -> bool {
+        let hysteresis = self.diff / 10;
+        let mid = cmp::max(self.first.duration, self.last.duration) - (self.diff / 2);
+        if self.first.duration < self.last.duration {
+            d > mid + hysteresis
+        } else {
+            d < mid - hysteresis
+        }
+    }
 }
 
-fn bisect_range(opts: &GlobalOptions, range: BisectRange) -> Result<(), Error> {
-    let hysteresis = range.diff / 10;
+/// Bisects the biggest change in the plot, building with the stored
+/// definition of its series' configuration and with its toolchain
+pub fn bisect(opts: &GlobalOptions, data: PlotData, configs: &BTreeMap<String, BuildConfig>) -> Result<(), Error> {
+    let range = find_biggest_range(data, |first, last| {
+        if is_parent(opts, &first.commit.id, &last.commit.id)? {
+            eprintln!("{} is parent of {}", first.commit.id.as_str(), last.commit.id.as_str());
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    })?;
+    eprintln!("bisecting {:#?}", range);
+    let config = NamedConfig {
+        name: range.key.config.clone(),
+        config: match range.key.config {
+            Some(ref name) => configs.get(name).cloned().ok_or_else(|| Error::UnknownConfig(name.clone()))?,
+            None => BuildConfig::default(),
+        },
+    };
+    let toolchain = range.key.toolchain_name.as_ref().map(|name| opts.build.toolchain(name));
+    bisect_range(opts, range, toolchain.as_ref(), &config)
+}
+
+fn bisect_range(opts: &GlobalOptions, range: BisectRange, toolchain: Option<&Toolchain>,
+                config: &NamedConfig) -> Result<(), Error> {
     let max = cmp::max(range.first.duration, range.last.duration);
     let min = cmp::min(range.first.duration, range.last.duration);
     let mid = max - (range.diff / 2);
     let ord = range.first.duration.cmp(&range.last.duration);
-    let is_new = |d: Duration| {
-        if ord == Ordering::Less {
-            d > mid + hysteresis
-        } else {
-            d < mid - hysteresis
-        }
-    };
 
-    println!("max: {:?}, min: {:?}, mid: {:?}, ord: {:?}, hyst: {:?}",
-             max, min, mid, ord, hysteresis);
+    eprintln!("max: {:?}, min: {:?}, mid: {:?}, ord: {:?}, hyst: {:?}",
+             max, min, mid, ord, range.diff / 10);
 
     let out = git::run_git(&opts.repo_path, "bisect",
                            &["start",
@@ -51,7 +77,7 @@ fn bisect_range(opts: &GlobalOptions, range: BisectRange) -> Result<(), Error> {
                              range.first.commit.id.as_str(),
                              "--term-old=old",
                              "--term-new=new"])?;
-    println!("{}", out);
+    eprintln!("{}", out);
 
     if !still_bisecting(&out) {
         git::run_git(&opts.repo_path, "bisect", &["reset"])?;
@@ -60,38 +86,34 @@ fn bisect_range(opts: &GlobalOptions, range: BisectRange) -> Result<(), Error> {
 
     let mut commit = parse_commit_from_stdout(&out)?;
 
-    let profile = range.profile;
+    let profile = range.key.profile;
     let project_path = opts.project_path();
 
     loop {
-        let results = cargo::time_build(project_path, profile)?;
+        let results = cargo::time_build(project_path, profile, toolchain, config, &opts.build)?;
 
-        if let Some(touched) = results.touched {
-            git::checkout_file(project_path, &touched)?;
+        if let Some(ref touched) = results.touched {
+            git::checkout_file(project_path, touched)?;
         }
 
-        if results.full.result == BuildResult::Failure {
-            println!("bad build - bisect skip");
+        if results.timings.iter().any(|t| t.result.failed()) {
+            eprintln!("bad build - bisect skip");
             git::run_git(&opts.repo_path, "bisect", &["skip"]);
             continue;
         }
 
-        let timing;
-        if range.rebuild_type == RebuildType::Full {
-            timing = results.full;
-        } else {
-            timing = results.partial.ok_or_else(|| Error::NoPartialBuild(commit.clone()))?;
-        }
+        let timing = results.get(range.key.rebuild_type)
+            .ok_or_else(|| Error::NoBuild(commit.clone(), range.key.rebuild_type))?;
 
         let out;
-        if is_new(timing.duration) {
+        if range.is_new(timing.duration) {
             out = git::run_git(&opts.repo_path, "bisect", &["new"])?;
         } else {
             out = git::run_git(&opts.repo_path, "bisect", &["old"])?;
         }
 
-        println!("{}", out);
-        println!("duration: {:?}", timing.duration);
+        eprintln!("{}", out);
+        eprintln!("duration: {:?}", timing.duration);
 
         if !still_bisecting(&out) {
             git::run_git(&opts.repo_path, "bisect", &["reset"])?;
@@ -124,14 +146,16 @@ fn parse_commit_from_stdout(s: &str) -> Result<CommitId, Error> {
     return Err(Error::BisectParse);
 }
 
-fn find_biggest_range(opts: &GlobalOptions, data: PlotData) -> Result<BisectRange, Error> {
+/// Finds the biggest change between consecutive points of any series,
+/// skipping points `is_adjacent` says have nothing between them to bisect
+pub fn find_biggest_range(data: PlotData, is_adjacent: impl Fn(&Entry, &Entry) -> Result<bool, Error>)
+                          -> Result<BisectRange, Error> {
     let mut biggest: Option<BisectRange> = None;
-    for series in data.0 {
+    for series in data.series {
         let mut prev: Option<Entry> = None;
         for entry in series.values {
             if let Some(p) = prev {
-                if is_parent(opts, &p.commit.id, &entry.commit.id)? {
-                    println!("{} is parent of {}", p.commit.id.as_str(), entry.commit.id.as_str());
+                if is_adjacent(&p, &entry)? {
                     // No bisection to be done
                     prev = Some(entry);
                     continue;
@@ -146,8 +170,7 @@ fn find_biggest_range(opts: &GlobalOptions, data: PlotData) -> Result<BisectRang
                 if let Some(cur_big) = biggest.clone() {
                     if cur_big.diff < diff {
                         biggest = Some(BisectRange {
-                            profile: series.profile,
-                            rebuild_type: series.rebuild_type,
+                            key: series.key.clone(),
                             first: p,
                             last: entry.clone(),
                             diff: diff,
@@ -155,8 +178,7 @@ fn find_biggest_range(opts: &GlobalOptions, data: PlotData) -> Result<BisectRang
                     }
                 } else {
                     biggest = Some(BisectRange {
-                        profile: series.profile,
-                        rebuild_type: series.rebuild_type,
+                        key: series.key.clone(),
                         first: p,
                         last: entry.clone(),
                         diff: diff,
@@ -182,12 +204,14 @@ pub enum Error {
     NotEnoughCommits,
     #[display(fmt = "git error")]
     Git(crate::git::Error),
-    #[display(fmt = "no partial build for {} during bisect", "_0.as_str()")]
-    NoPartialBuild(CommitId),
+    #[display(fmt = "no {} build of {} during bisect. add it to --rebuild", "_1.as_ref()", "_0.as_str()")]
+    NoBuild(CommitId, RebuildType),
     #[display(fmt = "parsing commit")]
     CommitIdParse(crate::commit_id::Error),
     #[display(fmt = "parsing bisect output")]
     BisectParse,
+    #[display(fmt = "build configuration '{}' is not in the database", _0)]
+    UnknownConfig(String),
     #[display(fmt = "running cargo")]
     Cargo(crate::cargo::Error),
 }
@@ -197,9 +221,10 @@ impl StdError for Error {
         match self {
             Error::NotEnoughCommits => None,
             Error::Git(ref e) => Some(e),
-            Error::NoPartialBuild(_) => None,
+            Error::NoBuild(..) => None,
             Error::CommitIdParse(ref e) => Some(e),
             Error::BisectParse => None,
+            Error::UnknownConfig(_) => None,
             Error::Cargo(ref e) => Some(e),
         }
     }
@@ -1,6 +1,7 @@
+use std::env;
 use std::io::Write;
 use std::path::PathBuf;
-use std::fs::OpenOptions;
+use std::fs::{self, File, OpenOptions};
 use std::time::{Instant, Duration};
 use chrono::{DateTime, Utc};
 use std::error::Error as StdError;
@@ -9,116 +10,370 @@ use std::path::Path;
 use crate::data::BuildResult;
 use crate::data::Timing;
 use crate::data::RebuildType;
-use std::process::Command;
+use std::process::{Child, Command, Output};
 use std::process::ExitStatus;
+use std::thread;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::opts::{BuildOptions, Toolchain};
+use crate::config::NamedConfig;
 
-pub struct BuildResultPair {
-    pub full: Timing,
-    pub partial: Option<Timing>,
+/// How often a build with a timeout is checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The wait before the first retry of a setup step. Doubles with each retry
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+pub struct BuildResults {
+    pub timings: Vec<Timing>,
+    /// A file touched for the partial build, to be restored
     pub touched: Option<PathBuf>,
 }
 
-pub fn time_build(path: &Path, profile: Profile) -> Result<BuildResultPair, Error> {
-    prime_toolchain(path)?;
-    cargo_clean(path)?;
-    cargo_fetch(path)?;
+impl BuildResults {
+    pub fn get(&self, rebuild_type: RebuildType) -> Option<&Timing> {
+        self.timings.iter().find(|t| t.rebuild_type == rebuild_type)
+    }
 
-    let full_result = cargo_time_build(path, profile, RebuildType::Full)?;
-    if full_result.result == BuildResult::Failure {
-        return Ok(BuildResultPair {
-            full: full_result,
-            partial: None,
-            touched: None,
-        });
+    /// Adds a build's timing. False if it failed.
+    fn push(&mut self, timing: Timing) -> bool {
+        let succeeded = !timing.result.failed();
+        self.timings.push(timing);
+        succeeded
     }
+}
 
-    let touched = touch_something(path)?;
-    let partial_result = cargo_time_build(path, profile, RebuildType::Partial)?;
+/// The toolchain builds run with and its `rustc -V`, recorded with each timing
+struct Rustc<'a> {
+    /// `None` for the toolchain the project selects
+    toolchain: Option<&'a Toolchain>,
+    version: String,
+}
 
-    Ok(BuildResultPair {
-        full: full_result,
-        partial: Some(partial_result),
-        touched: Some(touched),
-    })
+/// Times each of `opts.rebuild_types`. Every build but deps-only follows a
+/// full build, which is recorded if requested or if it fails. Stops at the
+/// first failure. If a setup step like `cargo fetch` or `cargo metadata`
+/// keeps failing, the timing records that instead.
+pub fn time_build(path: &Path, profile: Profile, toolchain: Option<&Toolchain>, config: &NamedConfig,
+                  opts: &BuildOptions) -> Result<BuildResults, Error> {
+    let wants = |rebuild_type| opts.rebuild_types.contains(&rebuild_type);
+    let mut results = BuildResults {
+        timings: vec![],
+        touched: None,
+    };
+
+    let rustc = Rustc {
+        toolchain,
+        version: prime_toolchain(path, toolchain)?,
+    };
+
+    let needs_full = opts.rebuild_types.iter().any(|&r| r != RebuildType::Deps);
+    if needs_full {
+        if let Some(failed) = setup(path, profile, RebuildType::Full, &rustc, config, opts)? {
+            results.timings.push(failed);
+            return Ok(results);
+        }
+
+        let full = cargo_time_build(path, profile, RebuildType::Full, &rustc, config, opts, &[])?;
+        let failed = full.result.failed();
+        if wants(RebuildType::Full) || failed {
+            results.timings.push(full);
+        }
+        if failed {
+            return Ok(results);
+        }
+
+        if wants(RebuildType::Partial) {
+            results.touched = Some(touch_something(path)?);
+            if !results.push(cargo_time_build(path, profile, RebuildType::Partial, &rustc, config, opts, &[])?) {
+                return Ok(results);
+            }
+        }
+
+        if wants(RebuildType::NoOp)
+            && !results.push(cargo_time_build(path, profile, RebuildType::NoOp, &rustc, config, opts, &[])?) {
+            return Ok(results);
+        }
+
+        if wants(RebuildType::Workspace) {
+            let log = SetupLog::new(opts, profile, &rustc, config, "workspace-setup")?;
+            let workspace = match workspace(path, toolchain, opts, &log)? {
+                Ok(workspace) => workspace,
+                Err(status) => {
+                    results.timings.push(log.failure(profile, RebuildType::Workspace, &rustc, config, "metadata", status)?);
+                    return Ok(results);
+                }
+            };
+            eprintln!("cleaning workspace crates {}", workspace.members.join(", "));
+            let output = retry("clean", opts, || {
+                let mut cmd = cargo_cmd(path, "clean", toolchain, opts)?;
+                for member in &workspace.members {
+                    cmd.arg("-p").arg(member);
+                }
+                if profile == Profile::Release {
+                    cmd.arg("--release");
+                }
+                cmd.stdout(log.file.try_clone()?).stderr(log.file.try_clone()?);
+                Ok(cmd)
+            })?;
+            if !output.status.success() {
+                results.timings.push(log.failure(profile, RebuildType::Workspace, &rustc, config, "clean", output.status)?);
+                return Ok(results);
+            }
+            if !results.push(cargo_time_build(path, profile, RebuildType::Workspace, &rustc, config, opts, &[])?) {
+                return Ok(results);
+            }
+        }
+    }
+
+    if wants(RebuildType::Deps) {
+        if let Some(failed) = setup(path, profile, RebuildType::Deps, &rustc, config, opts)? {
+            results.timings.push(failed);
+            return Ok(results);
+        }
+        let log = SetupLog::new(opts, profile, &rustc, config, "deps-setup")?;
+        let deps = match workspace(path, toolchain, opts, &log)? {
+            Ok(workspace) => workspace.deps,
+            Err(status) => {
+                results.timings.push(log.failure(profile, RebuildType::Deps, &rustc, config, "metadata", status)?);
+                return Ok(results);
+            }
+        };
+        if deps.is_empty() {
+            eprintln!("no dependencies to build");
+        } else {
+            results.push(cargo_time_build(path, profile, RebuildType::Deps, &rustc, config, opts, &deps)?);
+        }
+    }
+
+    Ok(results)
 }
 
-fn toolchain_cmd(path: &Path, cmd: &str) -> Result<Command, Error> {
+/// Runs `cmd` from `toolchain`, else from the toolchain the project selects
+fn toolchain_cmd(path: &Path, cmd: &str, toolchain: Option<&Toolchain>) -> Result<Command, Error> {
     let path = path.canonicalize()?;
-    let mut cmd = Command::new(cmd);
+    let mut cmd = match toolchain {
+        Some(&Toolchain { path: Some(ref root), .. }) => {
+            // Relative to where maptime runs, not the project
+            let bin = env::current_dir()?.join(root).join("bin");
+            // rustc builds like `build/<host>/stage1` have no cargo, but the
+            // one on the PATH can drive their rustc
+            let program = bin.join(cmd);
+            let program = if cmd == "cargo" && !program.exists() { PathBuf::from(cmd) } else { program };
+            // cargo would otherwise find rustc on the PATH
+            let mut cmd = Command::new(program);
+            cmd.env("RUSTC", bin.join("rustc"));
+            cmd.env("RUSTDOC", bin.join("rustdoc"));
+            cmd
+        }
+        _ => Command::new(cmd),
+    };
     cmd.current_dir(path);
     // FIME: This makes it so people can't use the env var, but lets maptime be run via cargo run
     cmd.env_remove("RUSTUP_TOOLCHAIN");
+    if let Some(&Toolchain { ref name, path: None }) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", name);
+    }
     Ok(cmd)
 }
 
-fn cargo_clean(path: &Path) -> Result<(), Error> {
-    println!("running `cargo clean`");
+/// `cargo <subcommand>`, with `--offline` or `--frozen` if requested
+fn cargo_cmd(path: &Path, subcommand: &str, toolchain: Option<&Toolchain>,
+             opts: &BuildOptions) -> Result<Command, Error> {
+    let mut cmd = toolchain_cmd(path, "cargo", toolchain)?;
+    cmd.arg(subcommand);
+    if opts.frozen {
+        cmd.arg("--frozen");
+    } else if opts.offline {
+        cmd.arg("--offline");
+    }
+    Ok(cmd)
+}
 
-    let mut cmd = toolchain_cmd(path, "cargo")?;
-    let cmd = cmd
-        .arg("clean");
+/// The log for one build or setup, named by when it started
+fn log_path(opts: &BuildOptions, start: DateTime<Utc>, profile: Profile, rustc: &Rustc,
+            config: &NamedConfig, step: &str) -> Result<PathBuf, Error> {
+    fs::create_dir_all(&opts.log_dir)?;
+    let config = config.name.as_ref().map(|n| format!("-{}", n)).unwrap_or_default();
+    let toolchain = rustc.toolchain.map(|t| format!("-{}", t.name)).unwrap_or_default();
+    Ok(opts.log_dir.join(format!("{}-{}{}{}-{}.log",
+                                 start.format("%Y%m%dT%H%M%S%.f"),
+                                 profile.as_ref(), config, toolchain, step)))
+}
 
-    let status = cmd.status()?;
+/// Runs `cargo clean` then `cargo fetch`, retrying each with backoff.
+/// Returns a timing of `rebuild_type` recording the failure if either
+/// never succeeds.
+fn setup(path: &Path, profile: Profile, rebuild_type: RebuildType, rustc: &Rustc,
+         config: &NamedConfig, opts: &BuildOptions) -> Result<Option<Timing>, Error> {
+    let log = SetupLog::new(opts, profile, rustc, config, "setup")?;
 
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Error::CargoClean)
+    for step in &["clean", "fetch"] {
+        let output = retry(step, opts, || {
+            let mut cmd = cargo_cmd(path, step, rustc.toolchain, opts)?;
+            cmd.stdout(log.file.try_clone()?).stderr(log.file.try_clone()?);
+            Ok(cmd)
+        })?;
+        if !output.status.success() {
+            return Ok(Some(log.failure(profile, rebuild_type, rustc, config, step, output.status)?));
+        }
     }
+
+    Ok(None)
 }
 
-fn cargo_fetch(path: &Path) -> Result<(), Error> {
-    println!("running `cargo fetch`");
+/// Runs `cargo <step>` until it succeeds or `opts.retries` retries have
+/// failed, doubling the wait each time. Returns the last attempt's output,
+/// of which only what `cmd` doesn't redirect is captured.
+fn retry(step: &str, opts: &BuildOptions, mut cmd: impl FnMut() -> Result<Command, Error>) -> Result<Output, Error> {
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        eprintln!("running `cargo {}`", step);
+        let output = cmd()?.output()?;
+        if output.status.success() || attempt == opts.retries {
+            return Ok(output);
+        }
+        attempt += 1;
+        eprintln!("`cargo {}` failed. retry {} of {} in {}s",
+                  step, attempt, opts.retries, delay.as_secs());
+        thread::sleep(delay);
+        delay *= 2;
+    }
+}
 
-    let mut cmd = toolchain_cmd(path, "cargo")?;
-    let cmd = cmd
-        .arg("fetch");
+/// The log of the steps before a build, which is recorded as a
+/// `SetupFailure` if one of them never succeeds
+struct SetupLog {
+    start: DateTime<Utc>,
+    path: PathBuf,
+    file: File,
+}
 
-    let status = cmd.status()?;
+impl SetupLog {
+    fn new(opts: &BuildOptions, profile: Profile, rustc: &Rustc, config: &NamedConfig,
+           step: &str) -> Result<SetupLog, Error> {
+        let start = Utc::now();
+        let path = log_path(opts, start, profile, rustc, config, step)?;
+        let file = File::create(&path)?;
+        Ok(SetupLog { start, path, file })
+    }
 
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Error::CargoFetch)
+    /// A timing of `rebuild_type` recording that `cargo <step>` failed
+    fn failure(&self, profile: Profile, rebuild_type: RebuildType, rustc: &Rustc, config: &NamedConfig,
+               step: &str, status: ExitStatus) -> Result<Timing, Error> {
+        let error = first_error(&self.path)?.unwrap_or_else(|| format!("cargo {} failed", step));
+        eprintln!("setup failed: {}. output in {}", error, self.path.display());
+        Ok(Timing {
+            profile,
+            rebuild_type,
+            start: self.start,
+            duration: Duration::default(),
+            result: BuildResult::SetupFailure,
+            toolchain: Some(rustc.version.clone()),
+            machine: None,
+            exit_code: status.code(),
+            error: Some(error),
+            log: Some(self.path.clone()),
+            config: config.name.clone(),
+            toolchain_name: rustc.toolchain.map(|t| t.name.clone()),
+        })
     }
 }
 
-fn prime_toolchain(path: &Path) -> Result<(), Error> {
-    println!("running `rustc -V` to prime the toolchain");
+/// Returns the `rustc -V` output, recorded with each timing
+pub fn prime_toolchain(path: &Path, toolchain: Option<&Toolchain>) -> Result<String, Error> {
+    match toolchain {
+        Some(toolchain) => eprintln!("running `rustc -V` to prime the {} toolchain", toolchain.name),
+        None => eprintln!("running `rustc -V` to prime the toolchain"),
+    }
 
-    let mut cmd = toolchain_cmd(path, "rustc")?;
-    let cmd = cmd
-        .arg("-V");
+    let version = rustc_version(path, toolchain, "-V")?;
+    eprintln!("{}", version);
+    Ok(version)
+}
 
-    let status = cmd.status()?;
+/// Returns the `rustc -vV` output, with the compiler's commit hash and date
+pub fn rustc_verbose_version(path: &Path, toolchain: Option<&Toolchain>) -> Result<String, Error> {
+    rustc_version(path, toolchain, "-vV")
+}
 
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Error::PrimeToolchain)
+fn rustc_version(path: &Path, toolchain: Option<&Toolchain>, flag: &str) -> Result<String, Error> {
+    let mut cmd = toolchain_cmd(path, "rustc", toolchain)?;
+    let cmd = cmd
+        .arg(flag);
+
+    match (cmd.output(), toolchain) {
+        (Ok(ref output), _) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        // Includes a toolchain path without a rustc
+        (_, Some(toolchain)) => Err(Error::ToolchainUnavailable(toolchain.name.clone())),
+        (Ok(_), None) => Err(Error::PrimeToolchain),
+        (Err(e), None) => Err(Error::Io(e)),
     }
 }
 
-fn cargo_time_build(path: &Path, profile: Profile, rebuild_type: RebuildType) -> Result<Timing, Error> {
-    println!("running `cargo build` for {} profile, {} rebuild", profile.as_ref(), rebuild_type.as_ref());
+/// Times `cargo build`, of only `packages` if any are given
+fn cargo_time_build(path: &Path, profile: Profile, rebuild_type: RebuildType, rustc: &Rustc,
+                    config: &NamedConfig, opts: &BuildOptions, packages: &[String]) -> Result<Timing, Error> {
+    let start_date = Utc::now();
+    let log = log_path(opts, start_date, profile, rustc, config, rebuild_type.as_ref())?;
+    let log_file = File::create(&log)?;
 
-    let mut cmd = toolchain_cmd(path, "cargo")?;
+    let config_name = config.name.as_ref().map(|n| format!(", {} config", n)).unwrap_or_default();
+    let toolchain_name = rustc.toolchain.map(|t| format!(", {} toolchain", t.name)).unwrap_or_default();
+    eprintln!("running `cargo build` for {} profile, {} rebuild{}{}. output in {}",
+              profile.as_ref(), rebuild_type.as_ref(), config_name, toolchain_name, log.display());
+
+    let mut cmd = cargo_cmd(path, "build", rustc.toolchain, opts)?;
     let mut cmd = cmd
         .env("CARGO_BUILD_PIPELINING", "true")
-        .arg("build");
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file);
 
     if profile == Profile::Release {
         cmd = cmd.arg("--release");
     }
 
-    let start_date = Utc::now();
+    for package in packages {
+        cmd = cmd.arg("-p").arg(package);
+    }
+
+    cmd = cmd.envs(&config.config.env);
+    if let Some(ref rustflags) = config.config.rustflags {
+        cmd = cmd.env("RUSTFLAGS", rustflags);
+    }
+    for value in &config.config.config {
+        cmd = cmd.arg("--config").arg(value);
+    }
+
     let start = Instant::now();
 
-    let status = cmd.status()?;
-    
+    let timeout = opts.timeout.map(|t| t.0);
+    // Only a build that may be killed gets a group of its own, since the
+    // terminal sends Ctrl-C to just its foreground group
+    let mut child = if timeout.is_some() { spawn_in_group(cmd)? } else { cmd.spawn()? };
+    let status = wait(&mut child, timeout)?;
+
     let dur = start.elapsed();
 
-    let res = if status.success() { BuildResult::Success } else { BuildResult::Failure };
+    let res = match status {
+        Some(status) if status.success() => BuildResult::Success,
+        Some(_) => BuildResult::Failure,
+        None => BuildResult::Timeout,
+    };
+
+    let error = if res.failed() { first_error(&log)? } else { None };
+    match res {
+        BuildResult::Success | BuildResult::SetupFailure => (),
+        BuildResult::Failure => {
+            eprintln!("build failed: {}", error.as_ref().map(String::as_str).unwrap_or("no error message"));
+        }
+        BuildResult::Timeout => {
+            eprintln!("build timed out after {:.0}s and was killed", dur.as_secs_f64());
+        }
+    }
 
     Ok(Timing {
         profile,
@@ -126,9 +381,233 @@ fn cargo_time_build(path: &Path, profile: Profile, rebuild_type: RebuildType) ->
         start: start_date,
         duration: dur,
         result: res,
+        toolchain: Some(rustc.version.clone()),
+        machine: None,
+        exit_code: status.and_then(|s| s.code()),
+        error,
+        log: Some(log),
+        config: config.name.clone(),
+        toolchain_name: rustc.toolchain.map(|t| t.name.clone()),
     })
 }
 
+/// The workspace's crates and what they depend on, from `cargo metadata`
+struct Workspace {
+    /// Package names of the workspace members
+    members: Vec<String>,
+    /// `name:version` specs of the members' direct dependencies, excluding
+    /// dev-dependencies and other members
+    deps: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    version: String,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    id: String,
+    dependencies: Vec<String>,
+}
+
+/// Reads the workspace with `cargo metadata`, retrying with backoff and
+/// logging its errors to `log`. `Err` with cargo's status if it never
+/// succeeds.
+fn workspace(path: &Path, toolchain: Option<&Toolchain>, opts: &BuildOptions,
+             log: &SetupLog) -> Result<Result<Workspace, ExitStatus>, Error> {
+    let output = retry("metadata", opts, || {
+        let mut cmd = cargo_cmd(path, "metadata", toolchain, opts)?;
+        cmd.arg("--format-version").arg("1").stderr(log.file.try_clone()?);
+        Ok(cmd)
+    })?;
+    if !output.status.success() {
+        return Ok(Err(output.status));
+    }
+    let metadata: Metadata = serde_json::from_slice(&output.stdout).map_err(Error::MetadataJson)?;
+
+    let package = |id: &str| metadata.packages.iter().find(|p| p.id == id);
+    let is_member = |id: &String| metadata.workspace_members.contains(id);
+
+    let members: Vec<_> = metadata.workspace_members.iter()
+        .filter_map(|id| package(id))
+        .collect();
+
+    let mut deps = vec![];
+    let nodes = metadata.resolve.iter().flat_map(|r| &r.nodes);
+    for node in nodes.filter(|n| is_member(&n.id)) {
+        let member = match package(&node.id) {
+            Some(member) => member,
+            None => continue,
+        };
+        for dep in node.dependencies.iter().filter(|id| !is_member(id)).filter_map(|id| package(id)) {
+            let built = member.dependencies.iter()
+                .any(|d| d.name == dep.name && d.kind.as_ref().map(String::as_str) != Some("dev"));
+            let spec = format!("{}:{}", dep.name, dep.version);
+            if built && !deps.contains(&spec) {
+                deps.push(spec);
+            }
+        }
+    }
+
+    Ok(Ok(Workspace {
+        members: members.iter().map(|p| p.name.clone()).collect(),
+        deps,
+    }))
+}
+
+/// Starts the command as the leader of a new process group, so it can be
+/// killed along with rustc, build scripts and linkers
+#[cfg(unix)]
+fn spawn_in_group(cmd: &mut Command) -> Result<Child, Error> {
+    use std::os::unix::process::CommandExt;
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+    Ok(cmd.spawn()?)
+}
+
+#[cfg(not(unix))]
+fn spawn_in_group(cmd: &mut Command) -> Result<Child, Error> {
+    Ok(cmd.spawn()?)
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut Child) -> Result<(), Error> {
+    // The group's ID is the leader's pid
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        Err(Error::Io(std::io::Error::last_os_error()))
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) -> Result<(), Error> {
+    Ok(child.kill()?)
+}
+
+/// The last of SIGINT or SIGTERM received while waiting on a build in its
+/// own process group, else 0
+#[cfg(unix)]
+static SIGNAL: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn record_signal(signal: libc::c_int) {
+    SIGNAL.store(signal as usize, Ordering::SeqCst);
+}
+
+/// Sets whether SIGINT and SIGTERM are caught rather than ending maptime
+#[cfg(unix)]
+fn catch_signals(catch: bool) {
+    let handler = if catch { record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t } else { libc::SIG_DFL };
+    for &signal in &[libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            libc::signal(signal, handler);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_signals(_catch: bool) {}
+
+/// Ends maptime with a signal caught while waiting, once the build's group
+/// has been killed
+#[cfg(unix)]
+fn exit_on_signal() {
+    let signal = SIGNAL.swap(0, Ordering::SeqCst);
+    if signal != 0 {
+        catch_signals(false);
+        unsafe {
+            libc::raise(signal as libc::c_int);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn exit_on_signal() {}
+
+#[cfg(unix)]
+fn signaled() -> bool {
+    SIGNAL.load(Ordering::SeqCst) != 0
+}
+
+#[cfg(not(unix))]
+fn signaled() -> bool {
+    false
+}
+
+/// Waits for the build to exit, killing it and everything it started once
+/// `timeout` passes. `None` if it was killed.
+///
+/// A build with a timeout runs in its own process group, out of reach of
+/// Ctrl-C, so a SIGINT or SIGTERM meanwhile kills the group before ending
+/// maptime.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Result<Option<ExitStatus>, Error> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(Some(child.wait()?)),
+    };
+
+    catch_signals(true);
+    let status = wait_timeout(child, timeout);
+    catch_signals(false);
+    exit_on_signal();
+    status
+}
+
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>, Error> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout || signaled() {
+            kill_group(child)?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The first line of cargo's output starting with `error`, like
+/// `error[E0425]: cannot find value`
+fn first_error(log: &Path) -> Result<Option<String>, Error> {
+    let output = fs::read(log)?;
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.lines()
+       .find(|line| line.starts_with("error"))
+       .map(|line| line.trim().to_string()))
+}
+
 fn touch_something(path: &Path) -> Result<PathBuf, Error> {
     let candidates = ["src/lib.rs", "src/main.rs"];
 
@@ -155,24 +634,24 @@ fn touch_something(path: &Path) -> Result<PathBuf, Error> {
 pub enum Error {
     #[display(fmt = "I/O error running cargo")]
     Io(std::io::Error),
-    #[display(fmt = "cargo clean failed")]
-    CargoClean,
-    #[display(fmt = "cargo fetch failed")]
-    CargoFetch,
     #[display(fmt = "unable to find file to touch for partial rebuild")]
     CantTouch,
+    #[display(fmt = "parsing cargo metadata")]
+    MetadataJson(serde_json::Error),
     #[display(fmt = "priming toolchain")]
     PrimeToolchain,
+    #[display(fmt = "can't run rustc from toolchain '{}'. install it with `rustup toolchain install`, or check its path", _0)]
+    ToolchainUnavailable(String),
 }
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             Error::Io(ref e) => Some(e),
-            Error::CargoClean => None,
-            Error::CargoFetch => None,
             Error::CantTouch => None,
+            Error::MetadataJson(ref e) => Some(e),
             Error::PrimeToolchain => None,
+            Error::ToolchainUnavailable(_) => None,
         }
     }
 }
@@ -182,3 +661,43 @@ impl From<std::io::Error> for Error {
         Error::Io(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::ffi::OsStr;
+
+    fn toolchain(dir: &TempDir) -> Toolchain {
+        Toolchain {
+            name: "local".to_string(),
+            path: Some(dir.path().to_path_buf()),
+        }
+    }
+
+    #[test]
+    fn toolchain_without_cargo_uses_path() {
+        let dir = TempDir::new("no-cargo");
+        fs::create_dir(dir.join("bin")).unwrap();
+        File::create(dir.join("bin/rustc")).unwrap();
+
+        let cmd = toolchain_cmd(dir.path(), "cargo", Some(&toolchain(&dir))).unwrap();
+        assert_eq!(cmd.get_program(), OsStr::new("cargo"));
+        let rustc = dir.join("bin/rustc");
+        assert!(cmd.get_envs().any(|(k, v)| k == "RUSTC" && v == Some(rustc.as_os_str())));
+
+        let cmd = toolchain_cmd(dir.path(), "rustc", Some(&toolchain(&dir))).unwrap();
+        assert_eq!(cmd.get_program(), rustc.as_os_str());
+    }
+
+    #[test]
+    fn toolchain_with_cargo_uses_its_own() {
+        let dir = TempDir::new("with-cargo");
+        fs::create_dir(dir.join("bin")).unwrap();
+        File::create(dir.join("bin/rustc")).unwrap();
+        File::create(dir.join("bin/cargo")).unwrap();
+
+        let cmd = toolchain_cmd(dir.path(), "cargo", Some(&toolchain(&dir))).unwrap();
+        assert_eq!(cmd.get_program(), dir.join("bin/cargo").as_os_str());
+    }
+}
@@ -0,0 +1,136 @@
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+use crate::analysis::{Comparison, Verdict};
+use crate::commit_id::CommitId;
+use crate::config::CheckConfig;
+use crate::plot::SeriesKey;
+use crate::table::Table;
+
+/// The budget for series without one in the config or on the command line
+pub const DEFAULT_MAX_REGRESSION: f64 = 5.0;
+
+/// The outcome of `maptime check`, written as JSON for CI
+#[derive(Serialize)]
+pub struct CheckResult {
+    pub base: String,
+    pub head: String,
+    pub passed: bool,
+    pub series: Vec<SeriesResult>,
+}
+
+#[derive(Serialize)]
+pub struct SeriesResult {
+    pub series: String,
+    /// Percent slowdown allowed
+    pub budget: f64,
+    pub base_median: Option<f64>,
+    pub head_median: Option<f64>,
+    /// Percent change of the medians
+    pub change: Option<f64>,
+    pub base_samples: usize,
+    pub head_samples: usize,
+    pub verdict: String,
+    pub passed: bool,
+}
+
+/// Checks each series against its budget. A series fails if HEAD is slower
+/// than the base by more than its budget and the slowdown isn't noise, or
+/// if HEAD has no successful builds where the base does.
+///
+/// Budgets come from the series' entry in the config, else `max_regression`,
+/// else the config's default.
+pub fn evaluate(base: &CommitId, head: &CommitId, comparisons: &[Comparison],
+                config: &CheckConfig, max_regression: Option<f64>) -> CheckResult {
+    let series: Vec<SeriesResult> = comparisons.iter().map(|c| {
+        let key = &c.key;
+        let budget = config.budget(key)
+            .or(max_regression)
+            .or(config.max_regression)
+            .unwrap_or(DEFAULT_MAX_REGRESSION);
+        let both = !c.before.is_empty() && !c.after.is_empty();
+        let change = if both { Some(c.change() * 100.0) } else { None };
+        let verdict = c.verdict();
+        let passed = match change {
+            Some(change) => change <= budget || verdict == Verdict::NoChange,
+            None => c.before.is_empty(),
+        };
+        SeriesResult {
+            series: key.name(),
+            budget,
+            base_median: if c.before.is_empty() { None } else { Some(c.before_median().as_secs_f64()) },
+            head_median: if c.after.is_empty() { None } else { Some(c.after_median().as_secs_f64()) },
+            change,
+            base_samples: c.before.len(),
+            head_samples: c.after.len(),
+            verdict: verdict.as_ref().to_string(),
+            passed,
+        }
+    }).collect();
+
+    CheckResult {
+        base: base.as_str().to_string(),
+        head: head.as_str().to_string(),
+        passed: series.iter().all(|s| s.passed),
+        series,
+    }
+}
+
+impl CheckResult {
+    pub fn failures(&self) -> usize {
+        self.series.iter().filter(|s| !s.passed).count()
+    }
+
+    pub fn print(&self) {
+        println!("base: {}", self.base);
+        println!("head: {}", self.head);
+        println!();
+
+        let header = ["series", "result", "verdict", "base", "head", "change", "budget"];
+        let mut table = Table::new(header.iter().map(|h| h.to_string()).collect(), 3);
+        for s in &self.series {
+            let secs = |s: Option<f64>| s.map(|s| format!("{:.2}s", s)).unwrap_or_else(|| "-".to_string());
+            table.push(vec![
+                s.series.clone(),
+                if s.passed { "pass" } else { "FAIL" }.to_string(),
+                s.verdict.clone(),
+                secs(s.base_median),
+                secs(s.head_median),
+                s.change.map(|c| format!("{:+.1}%", c)).unwrap_or_default(),
+                format!("{}%", s.budget),
+            ]);
+        }
+        table.print();
+        println!();
+
+        if self.passed {
+            println!("check passed");
+        } else {
+            println!("check failed: {} series over budget", self.failures());
+        }
+    }
+
+    pub fn write(&self, file: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).map_err(Error::Json)?;
+        fs::write(file, json).map_err(Error::Io)?;
+        eprintln!("check result in {}", file.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "serializing check result")]
+    Json(serde_json::Error),
+    #[display(fmt = "writing check result")]
+    Io(std::io::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Json(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use crate::data::BuildConfig;
+use crate::plot::{self, SeriesKey};
+
+/// The name of the configuration with no overrides
+pub const DEFAULT_CONFIG: &str = "default";
+
+/// Settings from `maptime.toml`. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub check: CheckConfig,
+    /// Build configurations by name, chosen with `--build-config`, e.g.
+    ///
+    /// ```toml
+    /// [configs.lld]
+    /// rustflags = "-C link-arg=-fuse-ld=lld"
+    ///
+    /// [configs.no-incr]
+    /// env = { CARGO_INCREMENTAL = "0" }
+    /// config = ["profile.dev.codegen-units=1"]
+    /// ```
+    #[serde(default)]
+    pub configs: BTreeMap<String, BuildConfig>,
+}
+
+/// A build configuration to time. `name` is `None` for the default.
+#[derive(Debug, Clone, Default)]
+pub struct NamedConfig {
+    pub name: Option<String>,
+    pub config: BuildConfig,
+}
+
+/// The `[check]` section, e.g.
+///
+/// ```toml
+/// [check]
+/// max-regression = 5
+///
+/// [check.budgets]
+/// "release+full" = 10
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckConfig {
+    /// Percent slowdown allowed for series without a budget
+    pub max_regression: Option<f64>,
+    /// Percent slowdown allowed per series, keyed like `dev+full`
+    #[serde(default)]
+    pub budgets: BTreeMap<String, f64>,
+}
+
+impl Config {
+    /// Loads the config, or the defaults if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(Error::Io(e)),
+        };
+        let config: Config = toml::from_str(&text).map_err(Error::Toml)?;
+        for key in config.check.budgets.keys() {
+            SeriesKey::from_str(key).map_err(Error::Series)?;
+        }
+        for name in config.configs.keys() {
+            // Names are part of series names like `dev+full+lld`
+            let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if name.is_empty() || !valid || name == DEFAULT_CONFIG {
+                return Err(Error::ConfigName(name.clone()));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Looks up build configurations by name
+    pub fn build_configs(&self, names: &[String]) -> Result<Vec<NamedConfig>, Error> {
+        names.iter().map(|name| {
+            if name == DEFAULT_CONFIG {
+                return Ok(NamedConfig::default());
+            }
+            match self.configs.get(name) {
+                Some(config) => Ok(NamedConfig {
+                    name: Some(name.clone()),
+                    config: config.clone(),
+                }),
+                None => Err(Error::UnknownConfig(name.clone())),
+            }
+        }).collect()
+    }
+}
+
+impl CheckConfig {
+    pub fn budget(&self, key: &SeriesKey) -> Option<f64> {
+        self.budgets.get(&key.name()).cloned()
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "reading config")]
+    Io(io::Error),
+    #[display(fmt = "parsing config")]
+    Toml(toml::de::Error),
+    #[display(fmt = "bad series in config")]
+    Series(plot::ParseError),
+    #[display(fmt = "bad build configuration name '{}'. use letters, digits, - and _, and not 'default'", _0)]
+    ConfigName(String),
+    #[display(fmt = "no build configuration '{}' in [configs]", _0)]
+    UnknownConfig(String),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(ref e) => Some(e),
+            Error::Toml(ref e) => Some(e),
+            Error::Series(ref e) => Some(e),
+            Error::ConfigName(_) => None,
+            Error::UnknownConfig(_) => None,
+        }
+    }
+}
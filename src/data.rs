@@ -4,39 +4,155 @@ use std::collections::BTreeMap;
 use chrono::{DateTime, Utc};
 use crate::commit_list::CommitInput;
 use crate::commit_id::CommitId;
+use std::path::PathBuf;
 use std::time::Duration;
+use std::str::FromStr;
+use crate::plot::SeriesKey;
 
-#[derive(Default, Serialize, Deserialize)]
+/// The schema version written to new databases. Older files are brought
+/// up to it by `migrate` when loaded.
+pub const VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Data {
+    /// 0 for files written before the schema was versioned
+    #[serde(default)]
+    pub version: u32,
     pub unresolved_commits: Vec<CommitInput>,
     pub commits: BTreeMap<CommitId, Commit>,
     pub timings: BTreeMap<CommitId, Vec<Timing>>,
+    /// Events added with `maptime annotate`, drawn as plot markers
+    #[serde(default)]
+    pub events: BTreeMap<CommitId, Vec<String>>,
+    /// The build configurations timings were built with, by name, as they
+    /// were defined when first used
+    #[serde(default)]
+    pub configs: BTreeMap<String, BuildConfig>,
+}
+
+/// Overrides applied to every build of a configuration, defined under
+/// `[configs.<name>]` in maptime.toml
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Environment variables, like `CARGO_INCREMENTAL = "0"`
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Set as `RUSTFLAGS`, like `-C link-arg=-fuse-ld=lld`
+    #[serde(default)]
+    pub rustflags: Option<String>,
+    /// Passed to cargo as `--config`, like `profile.dev.codegen-units=1`
+    #[serde(default)]
+    pub config: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Commit {
     pub id: CommitId,
+    /// The committer date
     pub date: DateTime<Utc>,
     pub note: Option<String>,
+    // The following are missing from commits resolved by older versions
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub author_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub parents: Vec<CommitId>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Timing {
     pub profile: Profile,
     pub rebuild_type: RebuildType,
     pub start: DateTime<Utc>,
     pub duration: Duration,
     pub result: BuildResult,
+    /// The `rustc -V` of the build. Missing from older timings
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// The machine that ran the build, set by `maptime merge`
+    #[serde(default)]
+    pub machine: Option<String>,
+    /// cargo's exit code. Missing if cargo was killed by a signal
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// The first compiler error of a failed build
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The file holding cargo's stdout and stderr
+    #[serde(default)]
+    pub log: Option<PathBuf>,
+    /// The name of the build configuration. Missing for the default, which
+    /// has no overrides
+    #[serde(default)]
+    pub config: Option<String>,
+    /// The toolchain chosen with `--toolchain`. Missing when the project's
+    /// own was used
+    #[serde(default)]
+    pub toolchain_name: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
-pub enum BuildResult { Success, Failure }
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum BuildResult {
+    Success,
+    Failure,
+    /// Killed after running longer than `--timeout`
+    Timeout,
+    /// `cargo clean` or `cargo fetch` failed, so the build never ran
+    SetupFailure,
+}
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
 pub enum Profile { Dev, Release }
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
-pub enum RebuildType { Full, Partial }
+pub enum RebuildType {
+    /// From a clean target dir, with dependencies already downloaded
+    Full,
+    /// After touching one source file
+    Partial,
+    /// After cleaning only the workspace's own crates, keeping built dependencies
+    Workspace,
+    /// With nothing changed since the last build
+    NoOp,
+    /// Only the dependencies, from a clean target dir
+    Deps,
+}
+
+impl Timing {
+    /// The series this build belongs to
+    pub fn key(&self) -> SeriesKey {
+        SeriesKey {
+            profile: self.profile,
+            rebuild_type: self.rebuild_type,
+            config: self.config.clone(),
+            toolchain_name: self.toolchain_name.clone(),
+        }
+    }
+
+    /// Whether two timings record the same build, as when one was copied
+    /// from another database
+    pub fn same_build(&self, other: &Timing) -> bool {
+        self.profile == other.profile
+            && self.rebuild_type == other.rebuild_type
+            && self.start == other.start
+            && self.config == other.config
+            && self.toolchain_name == other.toolchain_name
+            && self.machine == other.machine
+    }
+
+    pub fn is_in(&self, key: &SeriesKey) -> bool {
+        self.profile == key.profile
+            && self.rebuild_type == key.rebuild_type
+            && self.config == key.config
+            && self.toolchain_name == key.toolchain_name
+    }
+}
 
 impl AsRef<str> for Profile {
     fn as_ref(&self) -> &str {
@@ -52,6 +168,93 @@ impl AsRef<str> for RebuildType {
         match self {
             RebuildType::Full => "full",
             RebuildType::Partial => "partial",
+            RebuildType::Workspace => "workspace",
+            RebuildType::NoOp => "noop",
+            RebuildType::Deps => "deps",
+        }
+    }
+}
+
+impl BuildResult {
+    /// Whether the build failed, timed out or couldn't be set up
+    pub fn failed(&self) -> bool {
+        *self != BuildResult::Success
+    }
+}
+
+impl AsRef<str> for BuildResult {
+    fn as_ref(&self) -> &str {
+        match self {
+            BuildResult::Success => "success",
+            BuildResult::Failure => "failure",
+            BuildResult::Timeout => "timeout",
+            BuildResult::SetupFailure => "setup-failure",
+        }
+    }
+}
+
+impl FromStr for BuildResult {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<BuildResult, ParseError> {
+        match s {
+            "success" => Ok(BuildResult::Success),
+            "failure" => Ok(BuildResult::Failure),
+            "timeout" => Ok(BuildResult::Timeout),
+            "setup-failure" => Ok(BuildResult::SetupFailure),
+            _ => Err(ParseError::BuildResult(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Profile, ParseError> {
+        match s {
+            "dev" => Ok(Profile::Dev),
+            "release" => Ok(Profile::Release),
+            _ => Err(ParseError::Profile(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for RebuildType {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<RebuildType, ParseError> {
+        match s {
+            "full" => Ok(RebuildType::Full),
+            "partial" => Ok(RebuildType::Partial),
+            "workspace" => Ok(RebuildType::Workspace),
+            "noop" => Ok(RebuildType::NoOp),
+            "deps" => Ok(RebuildType::Deps),
+            _ => Err(ParseError::RebuildType(s.to_string())),
+        }
+    }
+}
+
+impl Commit {
+    /// The user's note, else the commit subject
+    pub fn description(&self) -> Option<&str> {
+        self.note.as_ref().or(self.subject.as_ref()).map(String::as_str)
+    }
+
+    pub fn short_id(&self) -> &str {
+        &self.id.as_str()[..10]
+    }
+}
+
+
+impl Default for Data {
+    fn default() -> Data {
+        Data {
+            version: VERSION,
+            unresolved_commits: vec![],
+            commits: BTreeMap::new(),
+            timings: BTreeMap::new(),
+            events: BTreeMap::new(),
+            configs: BTreeMap::new(),
         }
     }
 }
@@ -62,4 +265,91 @@ impl Data {
         commits.sort_by_key(|c| c.date);
         commits.into_iter().map(|c| c.id.clone()).collect()
     }
+
+    /// Durations of the commit's successful builds of one series
+    pub fn samples(&self, commit: &CommitId, key: &SeriesKey) -> Vec<Duration> {
+        self.timings.get(commit).into_iter().flatten()
+            .filter(|t| t.result == BuildResult::Success)
+            .filter(|t| t.is_in(key))
+            .map(|t| t.duration)
+            .collect()
+    }
+
+    /// Like `samples`, but only from builds on `machine`. `None` selects
+    /// untagged builds.
+    pub fn machine_samples(&self, commit: &CommitId, key: &SeriesKey, machine: Option<&str>) -> Vec<Duration> {
+        self.timings.get(commit).into_iter().flatten()
+            .filter(|t| t.result == BuildResult::Success)
+            .filter(|t| t.is_in(key))
+            .filter(|t| t.machine.as_ref().map(String::as_str) == machine)
+            .map(|t| t.duration)
+            .collect()
+    }
+
+    /// Every series with timings, in plotting order
+    pub fn series_keys(&self) -> Vec<SeriesKey> {
+        let mut variants: Vec<_> = self.timings.values().flatten()
+            .map(|t| (t.config.clone(), t.toolchain_name.clone()))
+            .collect();
+        // The default configuration and the project's toolchain first
+        variants.sort();
+        variants.dedup();
+        SeriesKey::all().into_iter()
+            .flat_map(|key| variants.iter().map(move |(config, toolchain_name)| SeriesKey {
+                config: config.clone(),
+                toolchain_name: toolchain_name.clone(),
+                ..key.clone()
+            }))
+            .filter(|key| self.timings.values().flatten().any(|t| t.is_in(key)))
+            .collect()
+    }
+
+    /// Every machine timings are tagged with, sorted. `None` stands for
+    /// untagged timings and comes last.
+    pub fn machines(&self) -> Vec<Option<String>> {
+        let mut machines: Vec<_> = self.timings.values().flatten().map(|t| t.machine.clone()).collect();
+        machines.sort_by(|a, b| match (a, b) {
+            (Some(a), Some(b)) => a.cmp(b),
+            _ => b.is_some().cmp(&a.is_some()),
+        });
+        machines.dedup();
+        machines
+    }
+
+    /// Drops timings from other machines than `machine`
+    pub fn retain_machine(&mut self, machine: &str) {
+        for timings in self.timings.values_mut() {
+            timings.retain(|t| t.machine.as_ref().map(String::as_str) == Some(machine));
+        }
+        self.timings.retain(|_, timings| !timings.is_empty());
+    }
+
+    /// The toolchain of the commit's latest timing with the toolchain the
+    /// project selects, if recorded
+    pub fn toolchain(&self, commit: &CommitId) -> Option<&str> {
+        self.timings.get(commit)?
+            .iter()
+            .filter(|t| t.toolchain_name.is_none())
+            .max_by_key(|t| t.start)
+            .and_then(|t| t.toolchain.as_ref())
+            .map(String::as_str)
+    }
+
+    pub fn sorted_commits_by_author_date(&self) -> Vec<CommitId> {
+        let mut commits: Vec<_> = self.commits.values().collect();
+        commits.sort_by_key(|c| c.author_date.unwrap_or(c.date));
+        commits.into_iter().map(|c| c.id.clone()).collect()
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum ParseError {
+    #[display(fmt = "unknown profile '{}'. expected dev or release", _0)]
+    Profile(String),
+    #[display(fmt = "unknown rebuild type '{}'. expected full, partial, workspace, noop or deps", _0)]
+    RebuildType(String),
+    #[display(fmt = "unknown build result '{}'. expected success, failure, timeout or setup-failure", _0)]
+    BuildResult(String),
 }
+
+impl StdError for ParseError { }
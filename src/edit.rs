@@ -0,0 +1,130 @@
+//! Removing and editing recorded data, for cleaning out bad measurements.
+//!
+//! Each function changes a `Data` in memory and describes what it changed,
+//! so callers can preview the changes before saving them.
+
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::commit_id::CommitId;
+use crate::data::{Data, Profile, RebuildType, Timing};
+use crate::plot::glob_match;
+
+/// Selects timings to remove. Conditions left `None` match everything.
+#[derive(Debug, Default)]
+pub struct TimingFilter {
+    pub commit: Option<CommitId>,
+    pub profile: Option<Profile>,
+    pub rebuild_type: Option<RebuildType>,
+    /// A build configuration. `Some(None)` is the default configuration
+    pub config: Option<Option<String>>,
+    pub toolchain_name: Option<String>,
+    /// Builds started before this
+    pub before: Option<DateTime<Utc>>,
+    /// A pattern like `rustc 1.38*` matching the toolchain or machine
+    pub env: Option<String>,
+    /// Only failed builds
+    pub failed: bool,
+}
+
+impl TimingFilter {
+    pub fn matches(&self, commit: &CommitId, timing: &Timing) -> bool {
+        let env_matches = |pattern: &str| {
+            timing.toolchain.iter().chain(timing.machine.iter()).any(|v| glob_match(pattern, v))
+        };
+        self.commit.as_ref().map_or(true, |c| c == commit)
+            && self.profile.map_or(true, |p| p == timing.profile)
+            && self.rebuild_type.map_or(true, |r| r == timing.rebuild_type)
+            && self.config.as_ref().map_or(true, |c| *c == timing.config)
+            && self.toolchain_name.as_ref().map_or(true, |t| timing.toolchain_name.as_ref() == Some(t))
+            && self.before.map_or(true, |before| timing.start < before)
+            && self.env.as_ref().map_or(true, |env| env_matches(env))
+            && (!self.failed || timing.result.failed())
+    }
+}
+
+/// Removes a commit along with its timings and events
+pub fn rm_commit(data: &mut Data, id: &CommitId) -> Vec<String> {
+    let mut changes = vec![];
+    if let Some(commit) = data.commits.remove(id) {
+        changes.push(format!("remove commit {} {}", id.as_str(), commit.description().unwrap_or("")));
+    }
+    let before = data.unresolved_commits.len();
+    data.unresolved_commits.retain(|c| c.id != *id);
+    if data.unresolved_commits.len() < before {
+        changes.push(format!("remove unresolved commit {}", id.as_str()));
+    }
+    for timing in data.timings.remove(id).into_iter().flatten() {
+        changes.push(describe_timing(id, &timing));
+    }
+    for event in data.events.remove(id).into_iter().flatten() {
+        changes.push(format!("remove event at {}: {}", &id.as_str()[..10], event));
+    }
+    changes
+}
+
+/// Removes every timing matching `filter`
+pub fn rm_timings(data: &mut Data, filter: &TimingFilter) -> Vec<String> {
+    let mut changes = vec![];
+    for (commit, timings) in data.timings.iter_mut() {
+        let (removed, kept) = timings.drain(..).partition(|t| filter.matches(commit, t));
+        *timings = kept;
+        let removed: Vec<Timing> = removed;
+        changes.extend(removed.iter().map(|t| describe_timing(commit, t)));
+    }
+    data.timings.retain(|_, timings| !timings.is_empty());
+    changes
+}
+
+/// Sets or, with `None`, clears a commit's note
+pub fn edit_note(data: &mut Data, id: &CommitId, note: Option<String>) -> Vec<String> {
+    let old = match data.commits.get_mut(id) {
+        Some(commit) => &mut commit.note,
+        None => match data.unresolved_commits.iter_mut().find(|c| c.id == *id) {
+            Some(commit) => &mut commit.note,
+            None => return vec![],
+        },
+    };
+    if *old == note {
+        return vec![];
+    }
+    let change = match (&*old, &note) {
+        (_, None) => format!("clear note of {}", id.as_str()),
+        (None, Some(new)) => format!("set note of {} to '{}'", id.as_str(), new),
+        (Some(prev), Some(new)) => format!("change note of {} from '{}' to '{}'", id.as_str(), prev, new),
+    };
+    *old = note;
+    vec![change]
+}
+
+/// Copies the database before it's changed. `maptime.json` is copied to
+/// `maptime.json.20191001T120000.bak`, with a counter added for more edits
+/// in the same second.
+pub fn backup(db_path: &Path) -> io::Result<PathBuf> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S").to_string();
+    let mut n = 0;
+    let backup = loop {
+        let mut name = db_path.as_os_str().to_owned();
+        match n {
+            0 => name.push(format!(".{}.bak", stamp)),
+            n => name.push(format!(".{}-{}.bak", stamp, n)),
+        }
+        let backup = PathBuf::from(name);
+        if !backup.exists() {
+            break backup;
+        }
+        n += 1;
+    };
+    fs::copy(db_path, &backup)?;
+    Ok(backup)
+}
+
+fn describe_timing(commit: &CommitId, timing: &Timing) -> String {
+    format!("remove timing of {} {} started {} {:.2}s {}",
+            &commit.as_str()[..10],
+            timing.key().name(),
+            timing.start.format("%Y-%m-%d %H:%M:%S"),
+            timing.duration.as_secs_f64(),
+            timing.result.as_ref())
+}
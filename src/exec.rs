@@ -1,18 +1,32 @@
 use crate::commit_id::CommitId;
 use std::convert::TryFrom;
-use crate::gnuplot::{self, PlotData, Series, Entry};
+use crate::plot::{self, PlotData, PlotOptions, SeriesKey, Backend, XAxis, YAxis};
 use crate::data::{BuildResult, Profile, RebuildType, Timing};
 use crate::cargo;
 use std::time::{Instant, Duration};
 use chrono::{DateTime, Utc};
 use crate::git;
-use std::path::Path;
-use atomic_blobject::AtomBlob;
+use std::path::{Path, PathBuf};
 use std::error::Error as StdError;
 use crate::opts::{Options, Command, GlobalOptions};
-use crate::data::Data;
+use crate::data::{self, Data};
+use crate::migrate;
+use crate::storage::{self, Database, TimingQuery, Backend as StorageBackend};
+use crate::lock::{self, LockMode};
 use crate::commit_list::CommitInput;
 use crate::bisect;
+use crate::analysis;
+use crate::report::{self, ReportOptions};
+use crate::table;
+use crate::output::{self, OutputFormat, RowFormat, CommitRow, TimingRow, ComparisonRow, FailureRow, PointRow,
+                    ToolchainChangeRow, MigrationRow, ChangeRow};
+use crate::check;
+use crate::export;
+use crate::edit::{self, TimingFilter};
+use crate::merge::{self, Source};
+use crate::config::{Config, NamedConfig, DEFAULT_CONFIG};
+use crate::trial::{self, Candidate};
+use crate::toolchain_bisect::{self, Bisection};
 
 pub fn run_command(opts: &Options) -> Result<(), Error> {
     match opts.cmd {
@@ -37,32 +51,152 @@ pub fn run_command(opts: &Options) -> Result<(), Error> {
         Command::FillGaps => {
             fill_gaps(&opts.global)
         }
-        Command::DumpResults => {
-            dump_results(&opts.global)
+        Command::DumpResults { ref commit, ref series, since, until } => {
+            let query = TimingQuery {
+                commit: match commit {
+                    Some(commit) => Some(git::read_commit_id(&opts.global.repo_path, commit)?),
+                    None => None,
+                },
+                series: series.clone(),
+                since: since.map(|d| d.0),
+                until: until.map(|d| d.0),
+                machine: opts.global.machine.clone(),
+            };
+            dump_results(&opts.global, &query)
         }
-        Command::Plot { ref file, ref no_labels } => {
-            plot(&opts.global, file, !no_labels)
+        Command::Failures => {
+            failures(&opts.global)
+        }
+        Command::Plot { ref file, backend, x_axis, ref plot_opts } => {
+            plot(&opts.global, file, backend, x_axis, plot_opts)
         }
         Command::Bisect => {
             bisect(&opts.global)
         }
+        Command::BisectToolchains { ref commit, ref toolchain_dir, sweep, ref plot, backend, ref plot_opts } => {
+            bisect_toolchains(&opts.global, commit, toolchain_dir.as_ref().map(PathBuf::as_path), sweep,
+                              plot.as_ref().map(PathBuf::as_path), backend, plot_opts)
+        }
+        Command::Report { ref html, ref title, ref commit_url, threshold, x_axis, ref tags, no_events, by_machine } => {
+            let report_opts = ReportOptions {
+                title,
+                commit_url: commit_url.as_ref().map(String::as_str),
+                threshold: threshold / 100.0,
+            };
+            report(&opts.global, html, &report_opts, x_axis, tags.as_ref().map(String::as_str), !no_events, by_machine)
+        }
+        Command::Table { x_axis, ref series, by_machine } => {
+            table(&opts.global, x_axis, series, by_machine)
+        }
+        Command::Compare { ref before, ref after, min_samples } => {
+            compare(&opts.global, before, after, min_samples)
+        }
+        Command::Try { ref base, ref patch, ref branch, runs } => {
+            let candidate = match (patch, branch) {
+                (Some(patch), _) => Candidate::Patch(patch),
+                (None, Some(branch)) => Candidate::Rev(branch),
+                (None, None) => Candidate::WorkingTree,
+            };
+            try_changes(&opts.global, base, candidate, runs)
+        }
+        Command::Check { ref base, max_regression, stored, runs, ref output } => {
+            check(&opts.global, base, max_regression.map(|p| p.0), stored, runs, output)
+        }
+        Command::Annotate { ref commit, ref event } => {
+            annotate(&opts.global, commit, event)
+        }
+        Command::Migrate { check } => {
+            migrate(&opts.global, check)
+        }
+        Command::RmCommit { ref commit, dry_run } => {
+            edit_db(&opts.global, dry_run, |data| {
+                let commit = find_commit(&opts.global, data, commit)?;
+                Ok(edit::rm_commit(data, &commit))
+            })
+        }
+        Command::Prune { before, ref env, dry_run } => {
+            if before.is_none() && env.is_none() {
+                return Err(Error::NoFilter);
+            }
+            let filter = TimingFilter {
+                before: before.map(|d| d.0),
+                env: env.clone(),
+                ..TimingFilter::default()
+            };
+            edit_db(&opts.global, dry_run, |data| Ok(edit::rm_timings(data, &filter)))
+        }
+        Command::RmTimings { ref commit, profile, rebuild_type, ref config, ref toolchain_name, failed, dry_run } => {
+            if commit.is_none() && profile.is_none() && rebuild_type.is_none()
+                && config.is_none() && toolchain_name.is_none() && !failed {
+                return Err(Error::NoFilter);
+            }
+            edit_db(&opts.global, dry_run, |data| {
+                let filter = TimingFilter {
+                    commit: match commit {
+                        Some(commit) => Some(find_commit(&opts.global, data, commit)?),
+                        None => None,
+                    },
+                    profile,
+                    rebuild_type,
+                    config: config.as_ref().map(|c| if c == DEFAULT_CONFIG { None } else { Some(c.clone()) }),
+                    toolchain_name: toolchain_name.clone(),
+                    failed,
+                    ..TimingFilter::default()
+                };
+                Ok(edit::rm_timings(data, &filter))
+            })
+        }
+        Command::EditNote { ref commit, ref note, dry_run } => {
+            edit_db(&opts.global, dry_run, |data| {
+                let commit = find_commit(&opts.global, data, commit)?;
+                let note = if note.is_empty() { None } else { Some(note.clone()) };
+                Ok(edit::edit_note(data, &commit, note))
+            })
+        }
+        Command::Convert { ref output } => {
+            convert(&opts.global, output)
+        }
+        Command::Merge { ref sources, ref output } => {
+            merge(sources, output)
+        }
+        Command::Export { ref csv } => {
+            export(&opts.global, csv)
+        }
+        Command::Import { ref files } => {
+            import(&opts.global, files)
+        }
     }
 }
 
-fn load_data(path: &Path) -> Result<AtomBlob<Data>, Error> {
-    Ok(AtomBlob::new(path)?)
+fn open_db(path: &Path, mode: LockMode) -> Result<Database, Error> {
+    Ok(storage::open(path, mode)?)
+}
+
+fn load_data(path: &Path) -> Result<Data, Error> {
+    Ok(open_db(path, LockMode::Shared)?.load()?)
 }
 
 fn list_commits(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+    let data = load_data(&opts.db_file)?;
+
+    if let Some(format) = opts.format.rows() {
+        if !data.unresolved_commits.is_empty() {
+            eprintln!("warning: {} unresolved commits not listed. run `maptime resolve-commits`", data.unresolved_commits.len());
+        }
+        let commits = data.sorted_commits();
+        let rows: Vec<_> = commits.iter().map(|c| CommitRow::new(&data.commits[c])).collect();
+        return Ok(output::write_rows(format, &rows)?);
+    }
 
     let mut some_commits = false;
     if !data.unresolved_commits.is_empty() { 
-       println!("unresolved commits");
+        println!("unresolved commits");
         println!("------------------");
         for commit in &data.unresolved_commits {
-            println!("{:?}", commit);
+            match commit.note {
+                Some(ref note) => println!("{} {}", commit.id.as_str(), note),
+                None => println!("{}", commit.id.as_str()),
+            }
         }
         println!();
         some_commits = true;
@@ -73,8 +207,24 @@ fn list_commits(opts: &GlobalOptions) -> Result<(), Error> {
         println!("commits");
         println!("-------");
         for commit in &commits {
-            let commit = data.commits.get(commit);
-            println!("{:?}", commit);
+            let commit = data.commits.get(commit).expect("sorted commit exists");
+            print!("{} {} {}",
+                   commit.id.as_str(),
+                   commit.date.format("%Y-%m-%d"),
+                   commit.author.as_ref().map(String::as_str).unwrap_or("<unknown author>"));
+            if !commit.tags.is_empty() {
+                print!(" ({})", commit.tags.join(", "));
+            }
+            println!();
+            if let Some(ref subject) = commit.subject {
+                println!("    {}", subject);
+            }
+            if let Some(ref note) = commit.note {
+                println!("    note: {}", note);
+            }
+            for event in data.events.get(&commit.id).into_iter().flatten() {
+                println!("    event: {}", event);
+            }
         }
         println!();
         some_commits = true;
@@ -89,11 +239,11 @@ fn list_commits(opts: &GlobalOptions) -> Result<(), Error> {
 }
 
 fn ingest_commit(opts: &GlobalOptions, commit: CommitInput) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let mut data = data.get_mut()?;
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
 
     data.unresolved_commits.push(commit);
-    Ok(data.commit()?)
+    Ok(db.save(&data)?)
 }
 
 fn ingest_commit_list(opts: &GlobalOptions, file: &Path) -> Result<(), Error> {
@@ -102,39 +252,29 @@ fn ingest_commit_list(opts: &GlobalOptions, file: &Path) -> Result<(), Error> {
     let list: Result<Vec<CommitInput>, _> = list.into_iter().collect();
     let list = list.map_err(|e| Error::CommitParse(e))?;
 
-    let mut data = load_data(&opts.db_file)?;
-    let mut data = data.get_mut()?;
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
     data.unresolved_commits.extend(list);
 
-    Ok(())
+    Ok(db.save(&data)?)
 }
 
 fn resolve_commits(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-
-    loop {
-        let full_commit = {
-            let data = data.get()?;
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
 
-            if let Some(basic_commit) = data.unresolved_commits.last().clone() {
-                git::read_commit(&opts.repo_path, &basic_commit)?
-            } else {
-                break;
-            }
-        };
-
-        let mut data = data.get_mut()?;
+    while let Some(basic_commit) = data.unresolved_commits.last() {
+        let full_commit = git::read_commit(&opts.repo_path, basic_commit)?;
         data.commits.insert(full_commit.id.clone(), full_commit);
         data.unresolved_commits.pop();
-        data.commit()?;
+        db.save(&data)?;
     }
 
     Ok(())
 }
 
 fn run_all(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+    let data = load_data(&opts.db_file)?;
 
     let mut plan = vec![];
     for commit in data.commits.keys() {
@@ -147,8 +287,7 @@ fn run_all(opts: &GlobalOptions) -> Result<(), Error> {
 }
 
 fn catch_up(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+    let data = load_data(&opts.db_file)?;
 
     let commits = data.sorted_commits();
 
@@ -166,7 +305,7 @@ fn catch_up(opts: &GlobalOptions) -> Result<(), Error> {
         }
     }
 
-    println!("{:#?}", plan);
+    eprintln!("{:#?}", plan);
 
     plan.sort_by_key(|p| p.1);
 
@@ -185,14 +324,13 @@ fn catch_up(opts: &GlobalOptions) -> Result<(), Error> {
 
     drop(data);
 
-    println!("catch-up plan: {:#?}", new_plan);
+    eprintln!("catch-up plan: {:#?}", new_plan);
 
     run(opts, &RunPlan(new_plan))
 }
 
 fn fill_gaps(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+    let data = load_data(&opts.db_file)?;
 
     let commits = data.sorted_commits();
 
@@ -211,184 +349,654 @@ fn fill_gaps(opts: &GlobalOptions) -> Result<(), Error> {
 struct RunPlan(Vec<(CommitId, u32)>);
 
 fn run(opts: &GlobalOptions, plan: &RunPlan) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
     let mut counter = 0;
+    let configs = Config::load(&opts.config)?.build_configs(&opts.build.configs)?;
 
     {
-        let data = data.get()?;
+        let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+        let mut data = db.load()?;
         if !data.unresolved_commits.is_empty() {
             return Err(Error::UnresolvedCommits);
         }
         if data.commits.is_empty() {
             return Err(Error::NoCommits);
         }
+        if record_configs(&mut data, &configs)? {
+            db.save(&data)?;
+        }
+    }
+
+    // Rather than find a missing toolchain after hours of builds
+    for toolchain in opts.build.toolchains().into_iter().flatten() {
+        cargo::prime_toolchain(opts.project_path(), Some(toolchain))?;
     }
 
     let start_commit = git::current_commit(&opts.repo_path)?;
-    println!("saving start commit {}", start_commit.as_ref());
+    eprintln!("saving start commit {}", start_commit.as_ref());
 
     for &(ref commit, count) in &plan.0 {
-        println!("checking out {}", commit.as_ref());
+        eprintln!("checking out {}", commit.as_ref());
         git::checkout(&opts.repo_path, &commit)?;
 
         let profiles = [Profile::Dev, Profile::Release];
 
         for _ in 0..count {
             for profile in profiles.iter().cloned() {
-                let project_path = opts.project_path();
-                let results = cargo::time_build(project_path, profile)?;
+                for config in &configs {
+                    for toolchain in opts.build.toolchains() {
+                        let project_path = opts.project_path();
+                        let results = cargo::time_build(project_path, profile, toolchain, config, &opts.build)?;
+
+                        // Lock only while writing, so other commands can use the
+                        // database between builds
+                        open_db(&opts.db_file, LockMode::Exclusive)?.add_timings(commit, &results.timings)?;
+
+                        if let Some(touched) = results.touched {
+                            // NB project_path, not repo_path
+                            git::checkout_file(project_path, &touched)?;
+                        }
+                    }
+                }
+            }
+        }
 
-                let mut data = data.get_mut()?;
-                data.timings.entry(commit.clone()).or_insert(vec![]).push(results.full);
+        counter += 1;
+    }
 
-                if let Some(partial_timing) = results.partial {
-                    data.timings.entry(commit.clone()).or_insert(vec![]).push(partial_timing);
-                }
+    eprintln!("restoring start commit {}", start_commit.as_ref());
+    git::checkout(&opts.repo_path, &start_commit)?;
 
-                data.commit()?;
+    eprintln!("done. timed {} commits", counter);
 
-                if let Some(touched) = results.touched {
-                    // NB project_path, not repo_path
-                    git::checkout_file(project_path, &touched)?;
-                }
+    Ok(())
+}
+
+/// Adds new build configurations to the database. A configuration's
+/// definition can't change once it has timings, since they would no longer
+/// be comparable. True if any were added.
+fn record_configs(data: &mut Data, configs: &[NamedConfig]) -> Result<bool, Error> {
+    let mut added = false;
+    for named in configs {
+        let name = match named.name {
+            Some(ref name) => name,
+            None => continue,
+        };
+        match data.configs.get(name) {
+            Some(stored) if *stored != named.config => return Err(Error::ConfigChanged(name.clone())),
+            Some(_) => (),
+            None => {
+                data.configs.insert(name.clone(), named.config.clone());
+                added = true;
             }
         }
+    }
+    Ok(added)
+}
 
-        counter += 1;
+fn dump_results(opts: &GlobalOptions, query: &TimingQuery) -> Result<(), Error> {
+    let timings = open_db(&opts.db_file, LockMode::Shared)?.query_timings(query)?;
+
+    if let Some(format) = opts.format.rows() {
+        let rows: Vec<_> = timings.iter().map(|(commit, t)| TimingRow::new(commit.as_str(), t)).collect();
+        return Ok(output::write_rows(format, &rows)?);
     }
 
-    println!("restoring start commit {}", start_commit.as_ref());
-    git::checkout(&opts.repo_path, &start_commit)?;
+    if timings.is_empty() {
+        println!("no timings");
+    }
 
-    println!("done. timed {} commits", counter);
+    let mut last_commit = None;
+    for (commit, timing) in &timings {
+        if last_commit != Some(commit) {
+            println!("commit {}", commit.as_str());
+            last_commit = Some(commit);
+        }
+        println!("  {} {:<7} {:<9} {:<10} {:<10} {:>8.2}s {:<7} {:<10} {}",
+                 timing.start.format("%Y-%m-%d %H:%M:%S"),
+                 timing.profile.as_ref(),
+                 timing.rebuild_type.as_ref(),
+                 timing.config.as_ref().map(String::as_str).unwrap_or(DEFAULT_CONFIG),
+                 timing.toolchain_name.as_ref().map(String::as_str).unwrap_or("-"),
+                 timing.duration.as_secs_f64(),
+                 timing.result.as_ref(),
+                 timing.machine.as_ref().map(String::as_str).unwrap_or("-"),
+                 timing.toolchain.as_ref().map(String::as_str).unwrap_or(""));
+    }
 
     Ok(())
 }
 
-fn dump_results(opts: &GlobalOptions) -> Result<(), Error> {
-    let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+fn failures(opts: &GlobalOptions) -> Result<(), Error> {
+    let data = read_data(opts)?;
+    let failures = analysis::failures(&data);
 
-    let commits = data.sorted_commits();
+    if let Some(format) = opts.format.rows() {
+        let rows: Vec<_> = failures.iter().map(FailureRow::new).collect();
+        return Ok(output::write_rows(format, &rows)?);
+    }
 
-    for commit in commits {
-        println!("commit {:?}", commit);
-        let timings = data.timings.get(&commit);
-        if let Some(timings) = timings {
-            for timing in timings {
-                println!("  {:?}", timing);
-            }
-        } else {
-            println!("  no timings");
+    if failures.is_empty() {
+        println!("no failed builds");
+    }
+
+    let mut last_commit = None;
+    for failure in &failures {
+        if last_commit != Some(&failure.commit) {
+            let commit = &data.commits[&failure.commit];
+            println!("commit {} {}", commit.short_id(), commit.description().unwrap_or(""));
+            last_commit = Some(&failure.commit);
+        }
+        let exit = match (&failure.latest.result, failure.latest.exit_code) {
+            (BuildResult::Timeout, _) => "timed out".to_string(),
+            (BuildResult::SetupFailure, _) => "setup failed".to_string(),
+            (_, Some(code)) => format!("exit code {}", code),
+            (_, None) => "exit code -".to_string(),
+        };
+        println!("  {} failed {}/{}, {}: {}",
+                 failure.key.name(),
+                 failure.failures, failure.builds, exit,
+                 failure.latest.error.as_ref().map(String::as_str).unwrap_or("no error recorded"));
+        if let Some(ref log) = failure.latest.log {
+            println!("    log: {}", log.display());
         }
     }
 
     Ok(())
 }
 
-fn plot(opts: &GlobalOptions, plotfile: &Path, labels: bool) -> Result<(), Error> {
-    let plotdata = get_plot_data(opts)?;
-    Ok(gnuplot::plot(plotdata, plotfile, labels)?)
+fn plot(opts: &GlobalOptions, plotfile: &Path, backend: Backend, x_axis: XAxis, plot_opts: &PlotOptions) -> Result<(), Error> {
+    let mut plotdata = get_plot_data(opts, x_axis, plot_opts.by_machine)?;
+    plotdata.select(&plot_opts.series);
+    if let Some(ref baseline) = plot_opts.baseline {
+        let baseline = git::read_commit_id(&opts.repo_path, baseline)?;
+        plotdata.rebase_to(&baseline);
+    } else if plot_opts.y_axis == YAxis::Percent {
+        plotdata.rebase_to_first();
+    }
+    if let Some(format) = opts.format.rows() {
+        return Ok(output::write_rows(format, &PointRow::all(&plotdata))?);
+    }
+    mark(opts, &mut plotdata, plot_opts.tags.as_ref().map(String::as_str), !plot_opts.no_events)?;
+    Ok(plot::plot(&plotdata, plotfile, backend, plot_opts)?)
+}
+
+fn mark(opts: &GlobalOptions, plotdata: &mut PlotData, tags: Option<&str>, events: bool) -> Result<(), Error> {
+    let data = load_data(&opts.db_file)?;
+
+    if let Some(pattern) = tags {
+        plotdata.mark_tags(pattern);
+    }
+    if events {
+        plotdata.mark_toolchains(&data);
+        plotdata.mark_events(&data);
+    }
+
+    Ok(())
 }
 
-fn get_plot_data(opts: &GlobalOptions) -> Result<PlotData, Error> {
+/// Loads a copy of the database with only the timings from `--machine`,
+/// if given
+fn read_data(opts: &GlobalOptions) -> Result<Data, Error> {
     let mut data = load_data(&opts.db_file)?;
-    let data = data.get()?;
+    if let Some(ref machine) = opts.machine {
+        data.retain_machine(machine);
+    }
+    Ok(data)
+}
 
-    let commits = data.sorted_commits();
+fn get_plot_data(opts: &GlobalOptions, x_axis: XAxis, by_machine: bool) -> Result<PlotData, Error> {
+    let data = read_data(opts)?;
 
-    let ref series_descs = [
-        (Profile::Dev, RebuildType::Full),
-        (Profile::Dev, RebuildType::Partial),
-        (Profile::Release, RebuildType::Full),
-        (Profile::Release, RebuildType::Partial),
-    ];
-    let mut serieses = Vec::new();
-    for series_desc in series_descs {
-        let mut new_series = Vec::new();
-        for commit in &commits {
-            let timings = data.timings.get(commit);
-            if let Some(timings) = timings {
-                let timings = timings.iter().filter(|t| t.result == BuildResult::Success);
-                let timings = timings.filter(|t| t.profile == series_desc.0);
-                let timings = timings.filter(|t| t.rebuild_type == series_desc.1);
-                let timings = timings.map(|t| t.duration);
-                let (count, sum) = timings.fold((0, Duration::default()), |(count, sum), duration| (count + 1, sum + duration));
-                if count == 0 {
-                    println!("warning: no timings for {}, profile {} type {}", commit.as_ref(), series_desc.0.as_ref(), series_desc.1.as_ref());
-                    continue;
+    let commits = match x_axis {
+        XAxis::CommitterDate | XAxis::Index => data.sorted_commits(),
+        XAxis::AuthorDate => data.sorted_commits_by_author_date(),
+        XAxis::Topological => git::topo_order(&opts.repo_path, &data.sorted_commits())?,
+    };
+
+    Ok(PlotData::from_data(&data, &commits, x_axis, by_machine))
+}
+
+fn report(opts: &GlobalOptions, file: &Path, report_opts: &ReportOptions, x_axis: XAxis,
+          tags: Option<&str>, events: bool, by_machine: bool) -> Result<(), Error> {
+    let mut plotdata = get_plot_data(opts, x_axis, by_machine)?;
+    mark(opts, &mut plotdata, tags, events)?;
+    let regressions = analysis::find_regressions(&plotdata, report_opts.threshold);
+    Ok(report::write_html(&plotdata, &regressions, report_opts, file)?)
+}
+
+fn table(opts: &GlobalOptions, x_axis: XAxis, series: &[SeriesKey], by_machine: bool) -> Result<(), Error> {
+    let mut plotdata = get_plot_data(opts, x_axis, by_machine)?;
+    plotdata.select(series);
+    if let Some(format) = opts.format.rows() {
+        return Ok(output::write_rows(format, &PointRow::all(&plotdata))?);
+    }
+    table::print_table(&plotdata);
+    Ok(())
+}
+
+fn compare(opts: &GlobalOptions, before: &str, after: &str, min_samples: Option<u32>) -> Result<(), Error> {
+    let before = git::read_commit_id(&opts.repo_path, before)?;
+    let after = git::read_commit_id(&opts.repo_path, after)?;
+
+    {
+        let data = load_data(&opts.db_file)?;
+        for commit in &[&before, &after] {
+            if !data.commits.contains_key(commit) {
+                return Err(Error::UnknownCommit((*commit).clone()));
+            }
+        }
+
+        if let Some(min_samples) = min_samples {
+            let mut plan = vec![];
+            for commit in &[&before, &after] {
+                let mut keys = vec![];
+                for key in SeriesKey::all().iter().filter(|k| opts.build.rebuild_types.contains(&k.rebuild_type)) {
+                    for config in &opts.build.configs {
+                        for toolchain in opts.build.toolchains() {
+                            keys.push(SeriesKey {
+                                config: if config == DEFAULT_CONFIG { None } else { Some(config.clone()) },
+                                toolchain_name: toolchain.map(|t| t.name.clone()),
+                                ..key.clone()
+                            });
+                        }
+                    }
                 }
-                let avg = sum / u32::try_from(count).expect("count should fit in u32");
-                let commit = data.commits.get(commit);
-                let commit = commit.expect("commit with timing should exists");
-                let commit = commit.clone();
-                let entry = Entry {
-                    commit,
-                    duration: avg,
-                };
-                new_series.push(entry);
-            } else {
-                println!("warning: no timings for {}", commit.as_ref());
+                let have = keys.iter()
+                    .map(|k| data.samples(commit, k).len())
+                    .min()
+                    .unwrap_or(0);
+                let need = min_samples.saturating_sub(u32::try_from(have).expect("small timing count"));
+                if need > 0 {
+                    eprintln!("{} needs {} more builds", commit.as_str(), need);
+                    plan.push(((*commit).clone(), need));
+                }
+            }
+            drop(data);
+            if !plan.is_empty() {
+                run(opts, &RunPlan(plan))?;
+            }
+        }
+    }
+
+    let data = read_data(opts)?;
+    let comparisons = analysis::compare(&data, &before, &after);
+
+    if let Some(format) = opts.format.rows() {
+        let rows: Vec<_> = comparisons.iter().map(ComparisonRow::new).collect();
+        return Ok(output::write_rows(format, &rows)?);
+    }
+
+    println!("before: {} {}", before.as_str(), data.commits[&before].description().unwrap_or(""));
+    println!("after:  {} {}", after.as_str(), data.commits[&after].description().unwrap_or(""));
+    println!();
+
+    table::print_comparisons(&comparisons);
+
+    Ok(())
+}
+
+fn try_changes(opts: &GlobalOptions, base: &str, candidate: Candidate, runs: u32) -> Result<(), Error> {
+    let comparisons = trial::try_changes(opts, base, candidate, runs)?;
+    if let Some(format) = opts.format.rows() {
+        let rows: Vec<_> = comparisons.iter().map(ComparisonRow::new).collect();
+        return Ok(output::write_rows(format, &rows)?);
+    }
+    println!();
+    table::print_comparisons(&comparisons);
+    Ok(())
+}
+
+fn check(opts: &GlobalOptions, base: &str, max_regression: Option<f64>,
+         stored: bool, runs: u32, output: &Path) -> Result<(), Error> {
+    let config = Config::load(&opts.config)?;
+    let base = git::read_commit_id(&opts.repo_path, base)?;
+
+    let (head, comparisons) = if stored {
+        let data = read_data(opts)?;
+        let base_timings = data.timings.get(&base).cloned()
+            .ok_or_else(|| Error::NoStoredTimings(base.clone()))?;
+
+        let (head, mut timings) = trial::time_rev(opts, "HEAD", runs)?;
+        timings.timings.insert(base.clone(), base_timings);
+        let comparisons = analysis::compare(&timings, &base, &head);
+        (head, comparisons)
+    } else {
+        let head = git::current_commit(&opts.repo_path)?;
+        let comparisons = trial::try_changes(opts, base.as_str(), Candidate::Rev("HEAD"), runs)?;
+        (head, comparisons)
+    };
+
+    let result = check::evaluate(&base, &head, &comparisons, &config.check, max_regression);
+    match opts.format {
+        OutputFormat::Text => {
+            println!();
+            result.print();
+        }
+        OutputFormat::Json => {
+            output::write_json(&result)?;
+        }
+        OutputFormat::Csv => {
+            output::write_rows(RowFormat::Csv, &result.series)?;
+        }
+    }
+    result.write(output)?;
+
+    if result.passed {
+        Ok(())
+    } else {
+        Err(Error::CheckFailed(result.failures()))
+    }
+}
+
+fn annotate(opts: &GlobalOptions, commit: &str, event: &str) -> Result<(), Error> {
+    let commit = git::read_commit_id(&opts.repo_path, commit)?;
+
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
+
+    if !data.commits.contains_key(&commit) {
+        return Err(Error::UnknownCommit(commit));
+    }
+
+    eprintln!("annotating {}: {}", commit.as_str(), event);
+    data.events.entry(commit).or_insert(vec![]).push(event.to_string());
+
+    Ok(db.save(&data)?)
+}
+
+fn migrate(opts: &GlobalOptions, check: bool) -> Result<(), Error> {
+    let path = &opts.db_file;
+    let _lock = lock::lock(path, if check { LockMode::Shared } else { LockMode::Exclusive })
+        .map_err(storage::Error::Lock)?;
+
+    let report = if check {
+        storage::check(path)?
+    } else {
+        storage::upgrade(path)?
+    };
+
+    if let Some(format) = opts.format.rows() {
+        let row = MigrationRow::new(path, report.as_ref(), !check);
+        return Ok(output::write_rows(format, &[row])?);
+    }
+
+    match report {
+        Some(report) => {
+            let verb = if check { "would migrate" } else { "migrated" };
+            println!("{} {} from version {} to {}", verb, path.display(), report.from, report.to);
+            for change in &report.changes {
+                println!("  {}", change);
             }
+            if !check {
+                println!("backup in {}", migrate::backup_path(path, report.from).display());
+            }
+        }
+        None if !path.exists() => {
+            println!("no database at {}", path.display());
+        }
+        None => {
+            println!("{} is up to date at version {}", path.display(), data::VERSION);
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies `change` to the database, printing what it changes first. Unless
+/// this is a dry run, backs up the database before saving.
+fn edit_db<F>(opts: &GlobalOptions, dry_run: bool, change: F) -> Result<(), Error>
+    where F: FnOnce(&mut Data) -> Result<Vec<String>, Error>
+{
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
+
+    let changes = change(&mut data)?;
+    if let Some(format) = opts.format.rows() {
+        let rows: Vec<_> = changes.iter().map(|change| ChangeRow { change, dry_run }).collect();
+        output::write_rows(format, &rows)?;
+    } else if changes.is_empty() {
+        println!("nothing to change");
+    } else {
+        for change in &changes {
+            println!("{}", change);
         }
+        if dry_run {
+            println!("dry run. {} changes not written", changes.len());
+        }
+    }
+
+    if changes.is_empty() || dry_run {
+        return Ok(());
+    }
+
+    let backup = edit::backup(&opts.db_file).map_err(Error::Backup)?;
+    eprintln!("backup in {}", backup.display());
+    Ok(db.save(&data)?)
+}
+
+/// Finds a commit in the database by ID or unique prefix, else resolves
+/// it with git, like a tag
+fn find_commit(opts: &GlobalOptions, data: &Data, commit: &str) -> Result<CommitId, Error> {
+    let ids = data.commits.keys().chain(data.unresolved_commits.iter().map(|c| &c.id));
+    let mut matches: Vec<&CommitId> = ids.filter(|id| id.as_str().starts_with(commit)).collect();
+    matches.dedup();
+    match matches.len() {
+        1 => return Ok(matches[0].clone()),
+        0 => { }
+        _ => return Err(Error::AmbiguousCommit(commit.to_string())),
+    }
 
-        serieses.push(Series {
-            profile: series_desc.0,
-            rebuild_type: series_desc.1,
-            values: new_series,
-        });
+    let id = git::read_commit_id(&opts.repo_path, commit)?;
+    let known = data.commits.contains_key(&id) || data.unresolved_commits.iter().any(|c| c.id == id);
+    if known {
+        Ok(id)
+    } else {
+        Err(Error::UnknownCommit(id))
     }
+}
+
+fn convert(opts: &GlobalOptions, output: &Path) -> Result<(), Error> {
+    if output.exists() {
+        return Err(Error::OutputExists(output.to_owned()));
+    }
+
+    let data = load_data(&opts.db_file)?;
+    open_db(output, LockMode::Exclusive)?.save(&data)?;
+    eprintln!("copied {} commits and {} timings to {}",
+              data.commits.len(), data.timings.values().map(Vec::len).sum::<usize>(), output.display());
 
-    Ok(PlotData(serieses))
+    Ok(())
+}
+
+fn merge(sources: &[Source], output: &Path) -> Result<(), Error> {
+    if output.exists() {
+        return Err(Error::OutputExists(output.to_owned()));
+    }
+
+    let mut merged = Data::default();
+    let mut stats = merge::MergeStats::default();
+
+    for source in sources {
+        eprintln!("merging {} as machine {}", source.path.display(), source.machine);
+        let data = load_data(&source.path)?;
+        merge::merge(&mut merged, data, &source.machine, &mut stats)?;
+    }
+
+    eprintln!("writing {} commits and {} timings to {}. skipped {} duplicate timings. {} conflicting notes",
+              stats.commits, stats.timings, output.display(), stats.duplicates, stats.note_conflicts);
+    Ok(open_db(output, LockMode::Exclusive)?.save(&merged)?)
+}
+
+fn export(opts: &GlobalOptions, file: &Path) -> Result<(), Error> {
+    let data = load_data(&opts.db_file)?;
+
+    let count = export::export_csv(&data, file)?;
+    eprintln!("exported {} timings to {}", count, file.display());
+
+    Ok(())
+}
+
+fn import(opts: &GlobalOptions, files: &[PathBuf]) -> Result<(), Error> {
+    let mut db = open_db(&opts.db_file, LockMode::Exclusive)?;
+    let mut data = db.load()?;
+
+    for file in files {
+        let stats = export::import_csv(&mut data, file)?;
+        eprintln!("imported {} timings and {} new commits from {}. skipped {} duplicate timings",
+                  stats.new_timings, stats.new_commits, file.display(), stats.duplicates);
+    }
+
+    Ok(db.save(&data)?)
 }
 
 fn bisect(opts: &GlobalOptions) -> Result<(), Error> {
-    let plotdata = get_plot_data(opts)?;
-    Ok(bisect::bisect(opts, plotdata)?)
+    let plotdata = get_plot_data(opts, XAxis::CommitterDate, false)?;
+    let configs = read_data(opts)?.configs;
+    Ok(bisect::bisect(opts, plotdata, &configs)?)
+}
+
+fn bisect_toolchains(opts: &GlobalOptions, commit: &str, toolchain_dir: Option<&Path>, sweep: usize,
+                     plotfile: Option<&Path>, backend: Backend, plot_opts: &PlotOptions) -> Result<(), Error> {
+    let toolchains = toolchain_bisect::list_toolchains(opts, toolchain_dir)?;
+    let mut bisection = toolchain_bisect::bisect_toolchains(opts, commit, &toolchains, sweep, &plot_opts.series)?;
+
+    if let Some(format) = opts.format.rows() {
+        output::write_rows(format, &[ToolchainChangeRow::new(&bisection)])?;
+    } else {
+        print_bisection(&bisection);
+    }
+
+    if let Some(file) = plotfile {
+        let plotdata = &mut bisection.plot;
+        plotdata.select(&plot_opts.series);
+        if plot_opts.y_axis == YAxis::Percent {
+            plotdata.rebase_to_first();
+        }
+        plot::plot(plotdata, file, backend, plot_opts)?;
+    }
+
+    Ok(())
+}
+
+fn print_bisection(bisection: &Bisection) {
+    table::print_table(&bisection.plot);
+    println!();
+    println!("{} went from {:.2}s with {} to {:.2}s with {}",
+             bisection.key.name(),
+             bisection.old_duration.as_secs_f64(), bisection.old.toolchain.name,
+             bisection.new_duration.as_secs_f64(), bisection.new.toolchain.name);
+    println!("  old: {}", bisection.old.commit.subject.as_ref().map(String::as_str).unwrap_or(""));
+    println!("  new: {}", bisection.new.commit.subject.as_ref().map(String::as_str).unwrap_or(""));
+    // Only rustup toolchains are known to be rust-lang/rust builds
+    if bisection.old.toolchain.path.is_none() && bisection.new.toolchain.path.is_none() {
+        println!("  https://github.com/rust-lang/rust/compare/{}...{}",
+                 bisection.old.commit.id.as_str(), bisection.new.commit.id.as_str());
+    }
 }
 
 #[derive(Display, Debug)]
 pub enum Error {
-    #[display(fmt = "loading blobject")]
-    AtomBlob(atomic_blobject::Error),
+    #[display(fmt = "accessing database")]
+    Storage(crate::storage::Error),
     #[display(fmt = "running git")]
     Git(crate::git::Error),
     #[display(fmt = "database contains unresolved commits. run `maptime resolve-commits`")]
     UnresolvedCommits,
     #[display(fmt = "no commits to test. add with `maptime ingest-commit`")]
     NoCommits,
+    #[display(fmt = "commit {} is not in the database", "_0.as_str()")]
+    UnknownCommit(CommitId),
+    #[display(fmt = "build configuration '{}' differs from the one its timings were built with. give it a new name", _0)]
+    ConfigChanged(String),
+    #[display(fmt = "merging databases")]
+    Merge(crate::merge::ConfigConflict),
     #[display(fmt = "running cargo")]
     Cargo(crate::cargo::Error),
     #[display(fmt = "commit list I/O")]
     CommitListIo(std::io::Error),
     #[display(fmt = "parsing commit")]
     CommitParse(parse_list::ParseListError<crate::commit_list::Error>),
-    #[display(fmt = "running gnuplot")]
-    GnuPlot(crate::gnuplot::Error),
+    #[display(fmt = "plotting")]
+    Plot(crate::plot::Error),
     #[display(fmt = "bisecting")]
     Bisect(crate::bisect::Error),
+    #[display(fmt = "bisecting toolchains")]
+    ToolchainBisect(crate::toolchain_bisect::Error),
+    #[display(fmt = "writing report")]
+    Report(crate::report::Error),
+    #[display(fmt = "trying changes")]
+    Trial(crate::trial::Error),
+    #[display(fmt = "loading config")]
+    Config(crate::config::Error),
+    #[display(fmt = "no timings for {} in the database", "_0.as_str()")]
+    NoStoredTimings(CommitId),
+    #[display(fmt = "checking build times")]
+    Check(crate::check::Error),
+    #[display(fmt = "{} series regressed past their budget", _0)]
+    CheckFailed(usize),
+    #[display(fmt = "writing output")]
+    Output(crate::output::Error),
+    #[display(fmt = "exporting or importing timings")]
+    Export(crate::export::Error),
+    #[display(fmt = "migrating database")]
+    Migrate(crate::migrate::Error),
+    #[display(fmt = "{} already exists", "_0.display()")]
+    OutputExists(PathBuf),
+    #[display(fmt = "'{}' matches more than one commit", _0)]
+    AmbiguousCommit(String),
+    #[display(fmt = "nothing selected. pass at least one option to choose what to remove")]
+    NoFilter,
+    #[display(fmt = "backing up database")]
+    Backup(std::io::Error),
+}
+
+impl Error {
+    /// 2 for a failed `maptime check`, so CI can tell it from other errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::CheckFailed(_) => 2,
+            _ => 1,
+        }
+    }
 }
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
-            Error::AtomBlob(ref e) => Some(e),
+            Error::Storage(ref e) => Some(e),
             Error::Git(ref e) => Some(e),
             Error::UnresolvedCommits => None,
             Error::NoCommits => None,
+            Error::UnknownCommit(_) => None,
+            Error::ConfigChanged(_) => None,
+            Error::Merge(ref e) => Some(e),
             Error::Cargo(ref e) => Some(e),
             Error::CommitListIo(ref e) => Some(e),
             Error::CommitParse(ref e) => Some(e),
-            Error::GnuPlot(ref e) => Some(e),
+            Error::Plot(ref e) => Some(e),
             Error::Bisect(ref e) => Some(e),
+            Error::ToolchainBisect(ref e) => Some(e),
+            Error::Report(ref e) => Some(e),
+            Error::Trial(ref e) => Some(e),
+            Error::Config(ref e) => Some(e),
+            Error::NoStoredTimings(_) => None,
+            Error::Check(ref e) => Some(e),
+            Error::CheckFailed(_) => None,
+            Error::Output(ref e) => Some(e),
+            Error::Export(ref e) => Some(e),
+            Error::Migrate(ref e) => Some(e),
+            Error::OutputExists(_) => None,
+            Error::AmbiguousCommit(_) => None,
+            Error::NoFilter => None,
+            Error::Backup(ref e) => Some(e),
         }
     }
 }
 
-impl From<atomic_blobject::Error> for Error {
-    fn from(e: atomic_blobject::Error) -> Error {
-        Error::AtomBlob(e) 
-   }
+impl From<crate::storage::Error> for Error {
+    fn from(e: crate::storage::Error) -> Error {
+        Error::Storage(e)
+    }
 }
 
 impl From<crate::git::Error> for Error {
@@ -397,15 +1005,27 @@ impl From<crate::git::Error> for Error {
     }
 }
 
+impl From<crate::merge::ConfigConflict> for Error {
+    fn from(e: crate::merge::ConfigConflict) -> Error {
+        Error::Merge(e)
+    }
+}
+
 impl From<crate::cargo::Error> for Error {
     fn from(e: crate::cargo::Error) -> Error {
         Error::Cargo(e)
     }
 }
 
-impl From<crate::gnuplot::Error> for Error {
-    fn from(e: crate::gnuplot::Error) -> Error {
-        Error::GnuPlot(e)
+impl From<crate::plot::Error> for Error {
+    fn from(e: crate::plot::Error) -> Error {
+        Error::Plot(e)
+    }
+}
+
+impl From<crate::toolchain_bisect::Error> for Error {
+    fn from(e: crate::toolchain_bisect::Error) -> Error {
+        Error::ToolchainBisect(e)
     }
 }
 
@@ -414,3 +1034,45 @@ impl From<crate::bisect::Error> for Error {
         Error::Bisect(e)
     }
 }
+
+impl From<crate::report::Error> for Error {
+    fn from(e: crate::report::Error) -> Error {
+        Error::Report(e)
+    }
+}
+
+impl From<crate::trial::Error> for Error {
+    fn from(e: crate::trial::Error) -> Error {
+        Error::Trial(e)
+    }
+}
+
+impl From<crate::config::Error> for Error {
+    fn from(e: crate::config::Error) -> Error {
+        Error::Config(e)
+    }
+}
+
+impl From<crate::check::Error> for Error {
+    fn from(e: crate::check::Error) -> Error {
+        Error::Check(e)
+    }
+}
+
+impl From<crate::output::Error> for Error {
+    fn from(e: crate::output::Error) -> Error {
+        Error::Output(e)
+    }
+}
+
+impl From<crate::export::Error> for Error {
+    fn from(e: crate::export::Error) -> Error {
+        Error::Export(e)
+    }
+}
+
+impl From<crate::migrate::Error> for Error {
+    fn from(e: crate::migrate::Error) -> Error {
+        Error::Migrate(e)
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::{DateTime, Utc};
+use std::error::Error as StdError;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::commit_id::{self, CommitId};
+use crate::data::{self, Commit, Data, Timing};
+
+/// One `Timing` with its commit, as a row of the file written by
+/// `maptime export --csv <file>`.
+///
+/// Dates are RFC 3339. The columns after `result` describe the build
+/// environment and may be empty.
+#[derive(Serialize, Deserialize)]
+pub struct ExportRow {
+    pub commit: String,
+    /// The committer date
+    pub date: String,
+    pub note: Option<String>,
+    pub profile: String,
+    pub rebuild_type: String,
+    pub start: String,
+    pub duration_ms: f64,
+    /// `success`, `failure`, `timeout` or `setup-failure`
+    pub result: String,
+    /// The `rustc -V` of the build
+    pub toolchain: Option<String>,
+    /// The machine that ran the build. Missing from older exports
+    #[serde(default)]
+    pub machine: Option<String>,
+    /// cargo's exit code. Missing from older exports
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// The first compiler error of a failed build
+    #[serde(default)]
+    pub error: Option<String>,
+    /// The build configuration's name, empty for the default. Definitions
+    /// aren't exported
+    #[serde(default)]
+    pub config: Option<String>,
+    /// The toolchain chosen with `--toolchain`, empty for the project's own
+    #[serde(default)]
+    pub toolchain_name: Option<String>,
+}
+
+/// Counts from `import_csv`
+#[derive(Debug, Default)]
+pub struct ImportStats {
+    pub new_commits: usize,
+    pub new_timings: usize,
+    /// Timings already in the database
+    pub duplicates: usize,
+}
+
+pub fn export_csv(data: &Data, file: &Path) -> Result<usize, Error> {
+    let mut writer = csv::Writer::from_path(file).map_err(Error::Csv)?;
+    let mut count = 0;
+    for id in data.sorted_commits() {
+        let commit = &data.commits[&id];
+        for timing in data.timings.get(&id).into_iter().flatten() {
+            writer.serialize(ExportRow::new(commit, timing)).map_err(Error::Csv)?;
+            count += 1;
+        }
+    }
+    writer.flush().map_err(Error::Io)?;
+    Ok(count)
+}
+
+/// Merges exported timings into `data`. A timing of the same build as an
+/// existing one is skipped. Commits
+/// not yet in the database are added with only their date and note,
+/// replacing any unresolved entry for them.
+pub fn import_csv(data: &mut Data, file: &Path) -> Result<ImportStats, Error> {
+    let mut reader = csv::Reader::from_path(file).map_err(Error::Csv)?;
+    let mut stats = ImportStats::default();
+
+    for (i, row) in reader.deserialize().enumerate() {
+        // The header is line 1
+        let line = i + 2;
+        let row: ExportRow = row.map_err(Error::Csv)?;
+        let (commit, timing) = row.parse().map_err(|e| Error::Row(line, e))?;
+
+        let id = commit.id.clone();
+        if !data.commits.contains_key(&id) {
+            data.unresolved_commits.retain(|c| c.id != id);
+            data.commits.insert(id.clone(), commit);
+            stats.new_commits += 1;
+        }
+
+        let timings = data.timings.entry(id).or_insert(vec![]);
+        if timings.iter().any(|t| t.same_build(&timing)) {
+            stats.duplicates += 1;
+        } else {
+            timings.push(timing);
+            stats.new_timings += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+impl ExportRow {
+    pub fn new(commit: &Commit, timing: &Timing) -> ExportRow {
+        ExportRow {
+            commit: commit.id.as_str().to_string(),
+            date: commit.date.to_rfc3339(),
+            note: commit.note.clone(),
+            profile: timing.profile.as_ref().to_string(),
+            rebuild_type: timing.rebuild_type.as_ref().to_string(),
+            start: timing.start.to_rfc3339(),
+            duration_ms: timing.duration.as_secs_f64() * 1000.0,
+            result: timing.result.as_ref().to_string(),
+            toolchain: timing.toolchain.clone(),
+            machine: timing.machine.clone(),
+            exit_code: timing.exit_code,
+            error: timing.error.clone(),
+            config: timing.config.clone(),
+            toolchain_name: timing.toolchain_name.clone(),
+        }
+    }
+
+    fn parse(self) -> Result<(Commit, Timing), RowError> {
+        let id = CommitId::from_str(&self.commit).map_err(RowError::CommitId)?;
+        let commit = Commit {
+            id,
+            date: parse_date(&self.date)?,
+            note: self.note,
+            author: None,
+            author_date: None,
+            subject: None,
+            parents: vec![],
+            tags: vec![],
+        };
+        if !self.duration_ms.is_finite() || self.duration_ms < 0.0 {
+            return Err(RowError::Duration(self.duration_ms));
+        }
+        let timing = Timing {
+            profile: self.profile.parse().map_err(RowError::Value)?,
+            rebuild_type: self.rebuild_type.parse().map_err(RowError::Value)?,
+            start: parse_date(&self.start)?,
+            duration: Duration::from_secs_f64(self.duration_ms / 1000.0),
+            result: self.result.parse().map_err(RowError::Value)?,
+            toolchain: self.toolchain,
+            machine: self.machine,
+            exit_code: self.exit_code,
+            error: self.error,
+            // Logs stay on the machine that ran the build
+            log: None,
+            config: self.config,
+            toolchain_name: self.toolchain_name,
+        };
+        Ok((commit, timing))
+    }
+}
+
+fn parse_date(s: &str) -> Result<DateTime<Utc>, RowError> {
+    let date = DateTime::parse_from_rfc3339(s).map_err(RowError::Date)?;
+    Ok(date.with_timezone(&Utc))
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "reading or writing csv")]
+    Csv(csv::Error),
+    #[display(fmt = "writing csv")]
+    Io(std::io::Error),
+    #[display(fmt = "bad row on line {}", _0)]
+    Row(usize, RowError),
+}
+
+#[derive(Debug, Display)]
+pub enum RowError {
+    #[display(fmt = "bad commit id")]
+    CommitId(commit_id::Error),
+    #[display(fmt = "bad date")]
+    Date(chrono::ParseError),
+    #[display(fmt = "bad duration {}", _0)]
+    Duration(f64),
+    #[display(fmt = "bad value")]
+    Value(data::ParseError),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Csv(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::Row(_, ref e) => Some(e),
+        }
+    }
+}
+
+impl StdError for RowError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            RowError::CommitId(ref e) => Some(e),
+            RowError::Date(ref e) => Some(e),
+            RowError::Duration(_) => None,
+            RowError::Value(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commit_list::CommitInput;
+    use crate::data::{BuildResult, Profile, RebuildType};
+    use crate::testing::TempDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const COMMIT: &str = "67a32593bf76464ff650f7b116af403d7f4613c9";
+
+    fn commit_id() -> CommitId {
+        CommitId::from_str(COMMIT).unwrap()
+    }
+
+    fn timing(rebuild_type: RebuildType, millis: u64) -> Timing {
+        Timing {
+            profile: Profile::Dev,
+            rebuild_type,
+            start: DateTime::parse_from_rfc3339("2019-10-01T12:00:00.123456789Z").unwrap().with_timezone(&Utc),
+            duration: Duration::from_millis(millis),
+            result: BuildResult::Success,
+            toolchain: Some("rustc 1.38.0".to_string()),
+            machine: None,
+            exit_code: Some(0),
+            error: None,
+            log: Some(PathBuf::from("maptime-logs/dev-full.log")),
+            config: Some("lld".to_string()),
+            toolchain_name: None,
+        }
+    }
+
+    fn data() -> Data {
+        let mut data = Data::default();
+        data.commits.insert(commit_id(), Commit {
+            id: commit_id(),
+            date: DateTime::parse_from_rfc3339("2019-10-01T00:00:00Z").unwrap().with_timezone(&Utc),
+            note: Some("a note".to_string()),
+            author: Some("a <a@b>".to_string()),
+            author_date: None,
+            subject: Some("subject".to_string()),
+            parents: vec![],
+            tags: vec![],
+        });
+        data.timings.insert(commit_id(), vec![timing(RebuildType::Full, 1500), timing(RebuildType::Partial, 250)]);
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new("round-trip");
+        let csv = dir.join("export.csv");
+        assert_eq!(export_csv(&data(), &csv).unwrap(), 2);
+
+        let mut imported = Data::default();
+        imported.unresolved_commits.push(CommitInput { id: commit_id(), note: None });
+        let stats = import_csv(&mut imported, &csv).unwrap();
+        assert_eq!((stats.new_commits, stats.new_timings, stats.duplicates), (1, 2, 0));
+        assert!(imported.unresolved_commits.is_empty());
+
+        let commit = &imported.commits[&commit_id()];
+        assert_eq!(commit.note.as_ref().map(String::as_str), Some("a note"));
+        assert_eq!(commit.date, data().commits[&commit_id()].date);
+
+        let timings = &imported.timings[&commit_id()];
+        for (imported, original) in timings.iter().zip(&data().timings[&commit_id()]) {
+            assert!(imported.same_build(original));
+            assert_eq!(imported.duration, original.duration);
+            assert_eq!(imported.result, original.result);
+            assert_eq!(imported.toolchain, original.toolchain);
+            assert_eq!(imported.exit_code, original.exit_code);
+            assert_eq!(imported.log, None);
+        }
+    }
+
+    #[test]
+    fn skips_duplicates() {
+        let dir = TempDir::new("duplicates");
+        let csv = dir.join("export.csv");
+        export_csv(&data(), &csv).unwrap();
+
+        let mut data = data();
+        let stats = import_csv(&mut data, &csv).unwrap();
+        assert_eq!((stats.new_commits, stats.new_timings, stats.duplicates), (0, 0, 2));
+        assert_eq!(data.timings[&commit_id()].len(), 2);
+    }
+
+    #[test]
+    fn keeps_builds_differing_in_machine_config_or_toolchain() {
+        let mut other = data();
+        let timings = other.timings.get_mut(&commit_id()).unwrap();
+        timings[0].machine = Some("ci".to_string());
+        timings[1].config = None;
+        let mut timing = timing(RebuildType::Full, 1500);
+        timing.toolchain_name = Some("nightly".to_string());
+        timings.push(timing);
+
+        let dir = TempDir::new("differing");
+        let csv = dir.join("export.csv");
+        export_csv(&other, &csv).unwrap();
+
+        let mut data = data();
+        let stats = import_csv(&mut data, &csv).unwrap();
+        assert_eq!((stats.new_timings, stats.duplicates), (3, 0));
+    }
+
+    #[test]
+    fn reports_bad_row_line() {
+        let dir = TempDir::new("bad-row");
+        let csv = dir.join("export.csv");
+        export_csv(&data(), &csv).unwrap();
+        let contents = fs::read_to_string(&csv).unwrap().replace(",250.0,", ",-250.0,");
+        fs::write(&csv, contents).unwrap();
+
+        match import_csv(&mut Data::default(), &csv) {
+            Err(Error::Row(3, RowError::Duration(d))) => assert_eq!(d, -250.0),
+            other => panic!("expected a bad duration on line 3, got {:?}", other.map(|s| s.new_timings)),
+        }
+    }
+}
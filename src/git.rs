@@ -5,16 +5,40 @@ use std::error::Error as StdError;
 use crate::commit_id::CommitId;
 use crate::commit_list::CommitInput;
 use crate::data::Commit;
-use std::process::Command;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::collections::BTreeSet;
 use std::str::FromStr;
 
 pub fn read_commit(path: &Path, commit: &CommitInput) -> Result<Commit, Error> {
-    let date = read_commit_date(path, commit.id.as_ref())?;
+    // Fields are separated by the ASCII unit separator, which can't appear
+    // in names or subjects.
+    let stdout = read_commit_stdout(path, commit.id.as_ref(), "%an <%ae>%x1f%aD%x1f%cD%x1f%P%x1f%s")?;
+    let fields: Vec<&str> = stdout.split('\x1f').collect();
+    if fields.len() != 5 {
+        return Err(Error::CommitFormat(stdout.clone()));
+    }
+
+    let author = fields[0].to_string();
+    let author_date = parse_date(fields[1])?;
+    let date = parse_date(fields[2])?;
+    let parents = fields[3].split_whitespace()
+        .map(CommitId::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::ReadCommitId(e))?;
+    let subject = fields[4].to_string();
+    let tags = read_tags(path, &commit.id)?;
 
     Ok(Commit {
         id: commit.id.clone(),
         date,
         note: commit.note.clone(),
+        author: Some(author),
+        author_date: Some(author_date),
+        subject: Some(subject),
+        parents,
+        tags,
     })
 }
 
@@ -22,15 +46,29 @@ pub fn current_commit(path: &Path) -> Result<CommitId, Error> {
     read_commit_id(path, "HEAD")
 }
 
-fn read_commit_date(path: &Path, commit: &str) -> Result<DateTime<Utc>, Error> {
-    let stdout = read_commit_stdout(path, commit, "%cD")?;
+/// The checked out branch, or `None` if HEAD is detached
+pub fn current_branch(path: &Path) -> Result<Option<String>, Error> {
+    let stdout = run_git(path, "rev-parse", &["--abbrev-ref", "HEAD"])?;
+    if stdout == "HEAD" {
+        Ok(None)
+    } else {
+        Ok(Some(stdout))
+    }
+}
 
-    let date = DateTime::parse_from_rfc2822(&stdout).map_err(|e| Error::DateParse(e))?;
+fn parse_date(s: &str) -> Result<DateTime<Utc>, Error> {
+    let date = DateTime::parse_from_rfc2822(s).map_err(|e| Error::DateParse(e))?;
     let date = DateTime::<Utc>::from(date);
 
     Ok(date)
 }
 
+pub fn read_tags(path: &Path, commit: &CommitId) -> Result<Vec<String>, Error> {
+    let stdout = run_git(path, "tag", &["--points-at", commit.as_str()])?;
+
+    Ok(stdout.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect())
+}
+
 pub fn read_commit_id(path: &Path, commit: &str) -> Result<CommitId, Error> {
     let stdout = read_commit_stdout(path, commit, "%H")?;
 
@@ -39,15 +77,89 @@ pub fn read_commit_id(path: &Path, commit: &str) -> Result<CommitId, Error> {
     Ok(id)
 }
 
+/// Sorts commits in git's topological order, oldest first
+pub fn topo_order(path: &Path, commits: &[CommitId]) -> Result<Vec<CommitId>, Error> {
+    // Through stdin, since a large database has too many ids for argv
+    let input: String = commits.iter().map(|c| format!("{}\n", c.as_str())).collect();
+    let stdout = run_git_stdin(path, "rev-list", &["--topo-order", "--reverse", "--stdin"], &input)?;
+
+    let wanted: BTreeSet<&CommitId> = commits.iter().collect();
+    let mut sorted = Vec::with_capacity(commits.len());
+    for line in stdout.lines() {
+        let id = CommitId::from_str(line).map_err(|e| Error::ReadCommitId(e))?;
+        if wanted.contains(&id) {
+            sorted.push(id);
+        }
+    }
+
+    Ok(sorted)
+}
+
 pub fn checkout(path: &Path, commit: &CommitId) -> Result<(), Error> {
     run_git_c(path, "checkout", commit.as_ref(), &[]).map(|_| ())
 }
 
+pub fn checkout_branch(path: &Path, branch: &str) -> Result<(), Error> {
+    run_git(path, "checkout", &[branch]).map(|_| ())
+}
+
+/// Checks out the branch, else the commit, that was checked out before
+/// timing other commits
+pub fn restore(path: &Path, start_branch: Option<&str>, start_commit: &CommitId) -> Result<(), Error> {
+    match start_branch {
+        Some(branch) => {
+            eprintln!("restoring start branch {}", branch);
+            checkout_branch(path, branch)
+        }
+        None => {
+            eprintln!("restoring start commit {}", start_commit.as_str());
+            checkout(path, start_commit)
+        }
+    }
+}
+
 pub fn checkout_file(path: &Path, file: &Path) -> Result<(), Error> {
     let file = file.to_str().ok_or(Error::BadPath)?;
     run_git_c(path, "checkout", "HEAD", &[file, "-f"]).map(|_| ())
 }
 
+/// Whether tracked files have uncommitted changes
+pub fn is_dirty(path: &Path) -> Result<bool, Error> {
+    let stdout = run_git(path, "status", &["--porcelain", "--untracked-files=no"])?;
+    Ok(!stdout.is_empty())
+}
+
+/// Makes a commit of the working tree's changes without touching the
+/// working tree or any ref. `None` if there are no changes.
+pub fn stash_create(path: &Path) -> Result<Option<CommitId>, Error> {
+    let stdout = run_git(path, "stash", &["create"])?;
+    if stdout.is_empty() {
+        return Ok(None);
+    }
+    let id = CommitId::from_str(&stdout).map_err(|e| Error::ReadCommitId(e))?;
+    Ok(Some(id))
+}
+
+/// Saves a commit from `stash_create` on the stash
+pub fn stash_store(path: &Path, commit: &CommitId, message: &str) -> Result<(), Error> {
+    run_git(path, "stash", &["store", "-m", message, commit.as_str()]).map(|_| ())
+}
+
+pub fn stash_pop(path: &Path) -> Result<(), Error> {
+    run_git(path, "stash", &["pop"]).map(|_| ())
+}
+
+/// Applies a patch to the working tree
+pub fn apply(path: &Path, patch: &Path) -> Result<(), Error> {
+    let patch = patch.canonicalize().map_err(|e| Error::GitExec(e))?;
+    let patch = patch.to_str().ok_or(Error::BadPath)?;
+    run_git(path, "apply", &[patch]).map(|_| ())
+}
+
+pub fn reset_hard(path: &Path) -> Result<(), Error> {
+    run_git(path, "reset", &["--hard"]).map(|_| ())
+}
+
 fn read_commit_stdout(path: &Path, commit: &str, format: &str) -> Result<String, Error> {
     run_git_c(path, "log", commit, &["-1", &format!("--pretty={}", format)])
 }
@@ -65,7 +177,7 @@ fn run_git_c(path: &Path, gitcmd: &str, commit: &str, args: &[&str]) -> Result<S
         .arg(commit)
         .args(args);
 
-    println!("executing git -C {} {} {} {}",
+    eprintln!("executing git -C {} {} {} {}",
              path.display(), gitcmd, commit, args.join(" "));
 
     let out = cmd.output().map_err(|e| Error::GitExec(e))?;
@@ -90,7 +202,7 @@ pub fn run_git(path: &Path, gitcmd: &str, args: &[&str]) -> Result<String, Error
         .arg(gitcmd)
         .args(args);
 
-    println!("executing git -C {} {} {}",
+    eprintln!("executing git -C {} {} {}",
              path.display(), gitcmd, args.join(" "));
 
     let out = cmd.output().map_err(|e| Error::GitExec(e))?;
@@ -106,6 +218,40 @@ pub fn run_git(path: &Path, gitcmd: &str, args: &[&str]) -> Result<String, Error
     Ok(stdout.to_string())
 }
 
+/// Like `run_git`, writing `input` to git's stdin
+fn run_git_stdin(path: &Path, gitcmd: &str, args: &[&str], input: &str) -> Result<String, Error> {
+    let mut cmd = Command::new("git");
+    let cmd = cmd
+        .arg("-C")
+        .arg(path)
+        .arg(gitcmd)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    eprintln!("executing git -C {} {} {} < {} lines",
+             path.display(), gitcmd, args.join(" "), input.lines().count());
+
+    let mut child = cmd.spawn().map_err(|e| Error::GitExec(e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    // Written from another thread so a full stdout pipe can't deadlock us
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = child.wait_with_output().map_err(|e| Error::GitExec(e))?;
+    writer.join().expect("stdin writer panicked").map_err(|e| Error::GitExec(e))?;
+
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        return Err(Error::Git { stderr });
+    }
+
+    let stdout = std::str::from_utf8(&out.stdout).map_err(|e| Error::RawDateParse(e))?;
+    let stdout = stdout.trim();
+
+    Ok(stdout.to_string())
+}
+
 #[derive(Debug)]
 pub enum Error {
     GitExec(std::io::Error),
@@ -114,6 +260,7 @@ pub enum Error {
     RawDateParse(std::str::Utf8Error),
     DateParse(chrono::ParseError),
     ReadCommitId(crate::commit_id::Error),
+    CommitFormat(String),
     BadPath,
 }
 
@@ -126,6 +273,7 @@ impl StdError for Error {
             Error::RawDateParse(ref e) => Some(e),
             Error::DateParse(ref e) => Some(e),
             Error::ReadCommitId(ref e) => Some(e),
+            Error::CommitFormat(_) => None,
             Error::BadPath => None,
         }
     }
@@ -152,6 +300,9 @@ impl Display for Error {
             Error::ReadCommitId(_) => {
                 write!(f, "reading commit id from git")
             }
+            Error::CommitFormat(ref out) => {
+                write!(f, "unexpected commit format from git: {}", out)
+            }
             Error::BadPath => {
                 write!(f, "bad git checkout file path")
             }
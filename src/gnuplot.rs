@@ -1,44 +1,84 @@
-use gnuplot::{Figure, PlotOption, Color, AxesCommon, LabelOption, AutoOption, TickOption, GnuplotInitError};
-use crate::data::Commit;
-use std::time::Duration;
-use chrono::{DateTime, Utc};
+use gnuplot::{Figure, PlotOption, Color, DashType, AxesCommon, LabelOption, AutoOption, TickOption, Tick, GnuplotInitError};
 use std::error::Error as StdError;
 use std::path::Path;
-use crate::data::{Profile, RebuildType};
+use crate::plot::{self, PlotData, PlotOptions, Renderer, Format, YAxis};
 
-pub struct PlotData(pub Vec<Series>);
-
-pub struct Series {
-    pub profile: Profile,
-    pub rebuild_type: RebuildType,
-    pub values: Vec<Entry>,
+pub struct GnuplotRenderer {
+    pub format: Format,
 }
 
-#[derive(Debug, Clone)]
-pub struct Entry {
-    pub commit: Commit,
-    pub duration: Duration,
+impl Renderer for GnuplotRenderer {
+    fn render(&self, data: &PlotData, opts: &PlotOptions, file: &Path) -> Result<(), plot::Error> {
+        let (width, height) = (opts.width, opts.height);
+        let terminal = match self.format {
+            Format::Svg => format!("svg size {}, {} dynamic mouse standalone", width, height),
+            Format::Png => format!("pngcairo size {}, {}", width, height),
+            // pdfcairo sizes are in inches
+            Format::Pdf => format!("pdfcairo size {}, {}", width as f64 / 100.0, height as f64 / 100.0),
+            Format::Html => {
+                return Err(plot::Error::UnsupportedFormat(plot::Backend::Gnuplot, self.format));
+            }
+        };
+        Ok(plot(data, opts, &terminal, file)?)
+    }
 }
 
-pub fn plot(data: PlotData, file: &Path, labels: bool) -> Result<(), Error> {
+pub fn plot(data: &PlotData, opts: &PlotOptions, terminal: &str, file: &Path) -> Result<(), Error> {
     let mut fg = Figure::new();
-    fg.set_title("build times");
+    fg.set_title(&opts.title);
+
+    let x_axis = data.x_axis;
+    let y_format = match data.y_axis {
+        YAxis::Seconds => "%gs",
+        YAxis::Percent => "%g%%",
+    };
 
     let mut fg2d = fg.axes2d();
-    fg2d.set_x_label("date", &[]);
-    fg2d.set_y_label("compile-time", &[]);
-    fg2d.set_x_time(true);
-    fg2d.set_x_ticks(Some((AutoOption::Auto, 0)), &[TickOption::Format("%Y-%m-%d")], &[LabelOption::Rotate(310_f64)]);
-    fg2d.set_y_ticks(Some((AutoOption::Auto, 0)), &[TickOption::Format("%gs")], &[]);
-
-    for series in &data.0 {
-        let x = series.values.iter().map(|e| e.commit.date.timestamp());
-        let y = series.values.iter().map(|e| e.duration);
-        fg2d.lines(x, y, &[PlotOption::Caption(&format!("{}+{}", series.profile.as_ref(), series.rebuild_type.as_ref()))]);
+    fg2d.set_y_label(data.y_axis.label(), &[]);
+    fg2d.set_y_ticks(Some((AutoOption::Auto, 0)), &[TickOption::Format(y_format)], &[]);
+    if opts.log_scale {
+        fg2d.set_y_log(Some(10.0));
+    }
+
+    fg2d.set_x_label(x_axis.label(), &[]);
+    if x_axis.is_time() {
+        fg2d.set_x_time(true);
+        fg2d.set_x_ticks(Some((AutoOption::Auto, 0)), &[TickOption::Format("%Y-%m-%d")], &[LabelOption::Rotate(310_f64)]);
+    } else {
+        fg2d.set_x_time(false);
+        let ticks = data.commit_ticks().into_iter().map(|(x, label)| Tick::Major(x, AutoOption::Fix(label)));
+        fg2d.set_x_ticks_custom(ticks, &[], &[LabelOption::Rotate(310_f64)]);
+    }
+
+    for series in &data.series {
+        let x = series.values.iter().map(|e| x_axis.x(e));
+        let y = series.values.iter().map(|e| series.y(e));
+        fg2d.lines(x, y, &[PlotOption::Caption(&series.caption())]);
+    }
+
+    if data.y_axis == YAxis::Percent {
+        let commits = data.commits();
+        if let (Some(first), Some(last)) = (commits.first(), commits.last()) {
+            let x = [x_axis.x(first), x_axis.x(last)];
+            fg2d.lines(&x, &[100.0, 100.0], &[PlotOption::Color("black"), PlotOption::LineStyle(DashType::Dash)]);
+        }
+    }
+
+    for marker in &data.markers {
+        use gnuplot::{ArrowOption, ArrowheadType, Coordinate};
+        let (r, g, b) = marker.kind.rgb();
+        let color = format!("#{:02x}{:02x}{:02x}", r, g, b);
+        fg2d.arrow(Coordinate::Axis(marker.x as _), Coordinate::Graph(0.0),
+                   Coordinate::Axis(marker.x as _), Coordinate::Graph(1.0),
+                   &[ArrowOption::ArrowType(ArrowheadType::NoArrow), ArrowOption::Color(&color),
+                     ArrowOption::LineStyle(DashType::Dash)]);
+        fg2d.label(&marker.label, Coordinate::Axis(marker.x as _), Coordinate::Graph(0.98),
+                   &[LabelOption::TextColor(&color), LabelOption::Rotate(90.0),
+                     LabelOption::TextAlign(gnuplot::AlignType::AlignRight)]);
     }
 
-    if labels {
-        for series in &data.0 {
+    if !opts.no_labels {
+        for series in &data.series {
             for v in series.values.iter() {
                 // Don't label commits called "prev" - uninteresting
                 if v.commit.note.as_ref().map(Borrow::borrow)  == Some("prev") {
@@ -47,10 +87,11 @@ pub fn plot(data: PlotData, file: &Path, labels: bool) -> Result<(), Error> {
 
                 use gnuplot::Coordinate;
                 use std::borrow::Borrow;
-                let label = format!("{}\\n{}\\n{}", v.commit.date.format("%Y-%m-%d"), v.commit.id.as_str(), v.commit.note.as_ref().map(Borrow::borrow).unwrap_or("<no description>"));
+                // gnuplot reads the escape rather than a newline
+                let label = v.hover_text(x_axis).replace('\n', "\\n");
                 fg2d.label(&label,
-                           Coordinate::Axis(v.commit.date.timestamp() as _),
-                           Coordinate::Axis(v.duration.as_secs() as _),
+                           Coordinate::Axis(x_axis.x(v) as _),
+                           Coordinate::Axis(series.y(v)),
                            &[LabelOption::Hypertext, LabelOption::MarkerSymbol('O'), LabelOption::MarkerSize(0.4)]);
             }
         }
@@ -59,12 +100,12 @@ pub fn plot(data: PlotData, file: &Path, labels: bool) -> Result<(), Error> {
     //fg.save_to_svg(&file.to_str().ok_or(Error::PlotFile)?,
     //               600, 400).map_err(Error::GnuplotInit)?;
 
-    fg.set_terminal("svg size 600, 400 dynamic mouse standalone", &file.to_str().ok_or(Error::PlotFile)?);
+    fg.set_terminal(terminal, &file.to_str().ok_or(Error::PlotFile)?);
     fg.show();
 
-    fg.echo(&mut std::io::stdout());
+    fg.echo(&mut std::io::stderr());
 
-    println!("plot in {}", file.display());
+    eprintln!("plot in {}", file.display());
 
     Ok(())
 }
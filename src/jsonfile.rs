@@ -0,0 +1,47 @@
+use atomic_blobject::AtomBlob;
+use std::path::Path;
+use crate::commit_id::CommitId;
+use crate::data::{Data, Timing};
+use crate::storage::{Error, Storage, TimingQuery};
+
+/// The whole database as one JSON file
+pub struct JsonStorage {
+    blob: AtomBlob<Data>,
+}
+
+impl JsonStorage {
+    pub fn open(path: &Path) -> Result<JsonStorage, Error> {
+        Ok(JsonStorage {
+            blob: AtomBlob::new(path)?,
+        })
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> Result<Data, Error> {
+        Ok(self.blob.get()?.clone())
+    }
+
+    fn save(&mut self, data: &Data) -> Result<(), Error> {
+        let mut blob = self.blob.get_mut()?;
+        *blob = data.clone();
+        Ok(blob.commit()?)
+    }
+
+    fn add_timings(&mut self, commit: &CommitId, timings: &[Timing]) -> Result<(), Error> {
+        let mut data = self.blob.get_mut()?;
+        data.timings.entry(commit.clone()).or_insert(vec![]).extend_from_slice(timings);
+        Ok(data.commit()?)
+    }
+
+    fn query_timings(&mut self, query: &TimingQuery) -> Result<Vec<(CommitId, Timing)>, Error> {
+        let data = self.blob.get()?;
+        let mut results = vec![];
+        for commit in data.sorted_commits() {
+            for timing in data.timings.get(&commit).into_iter().flatten().filter(|t| query.matches(&commit, t)) {
+                results.push((commit.clone(), timing.clone()));
+            }
+        }
+        Ok(results)
+    }
+}
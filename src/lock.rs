@@ -0,0 +1,119 @@
+//! Advisory locking of the database, so concurrent maptime processes don't
+//! overwrite each other's changes.
+//!
+//! Locks are taken on `<db>.lock` next to the database, which holds the
+//! pid of the latest holder for error messages. They are held only while
+//! reading or writing, never across builds, so contention is brief.
+
+use fs2::FileExt;
+use std::error::Error as StdError;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum LockMode {
+    /// For reading. Any number of processes may hold it
+    Shared,
+    /// For writing. Excludes every other lock
+    Exclusive,
+}
+
+/// Held until dropped
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+/// Locks the database at `db_path`, waiting up to `TIMEOUT` for other
+/// processes to finish
+pub fn lock(db_path: &Path, mode: LockMode) -> Result<Lock, Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(lock_path(db_path))
+        .map_err(Error::Io)?;
+
+    let start = Instant::now();
+    let mut warned = false;
+    loop {
+        let locked = match mode {
+            LockMode::Shared => FileExt::try_lock_shared(&file),
+            LockMode::Exclusive => FileExt::try_lock_exclusive(&file),
+        };
+        match locked {
+            Ok(()) => break,
+            Err(ref e) if e.kind() == fs2::lock_contended_error().kind() => {
+                let holder = read_pid(&mut file);
+                if start.elapsed() >= TIMEOUT {
+                    return Err(holder.map_or(Error::InUseByUnknown, Error::InUse));
+                }
+                if !warned {
+                    match holder {
+                        Some(pid) => eprintln!("waiting for pid {} to release the database", pid),
+                        None => eprintln!("waiting for another process to release the database"),
+                    }
+                    warned = true;
+                }
+                thread::sleep(POLL);
+            }
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+
+    // Shared holders may interleave these writes. That only garbles the
+    // pid in a later error message.
+    file.set_len(0)
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .and_then(|_| writeln!(file, "{}", std::process::id()))
+        .map_err(Error::Io)?;
+
+    Ok(Lock { file })
+}
+
+/// `maptime.json` is locked through `maptime.json.lock`
+pub fn lock_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.lines().next()?.trim().parse().ok()
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "locking database")]
+    Io(io::Error),
+    #[display(fmt = "database is in use by pid {}", _0)]
+    InUse(u32),
+    #[display(fmt = "database is in use by another process")]
+    InUseByUnknown,
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(ref e) => Some(e),
+            Error::InUse(_) => None,
+            Error::InUseByUnknown => None,
+        }
+    }
+}
@@ -14,8 +14,32 @@ mod data;
 mod exec;
 mod git;
 mod opts;
+mod plot;
+mod native;
+mod pdf;
+#[cfg(feature = "gnuplot")]
 mod gnuplot;
 mod bisect;
+mod analysis;
+mod report;
+mod terminal;
+mod table;
+mod trial;
+mod toolchain_bisect;
+mod config;
+mod check;
+mod output;
+mod export;
+mod merge;
+mod migrate;
+mod storage;
+mod lock;
+mod edit;
+mod jsonfile;
+mod sqlite;
+mod stats;
+#[cfg(test)]
+mod testing;
 
 fn main() {
     use std::process;
@@ -25,8 +49,6 @@ fn main() {
 
     let opts = Options::from_args();
 
-    eprintln!("opts: {:#?}", opts);
-
     if let Err(e) = exec::run_command(&opts) {
         eprintln!("error: {}", e);
 
@@ -36,6 +58,6 @@ fn main() {
             maybe_source = source.source();
         }
 
-        process::exit(1);
+        process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,159 @@
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::data::{Commit, Data, Timing};
+
+/// A database to merge, written `NAME=PATH` or just `PATH`. Untagged
+/// timings are tagged with `NAME`, else the file stem.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub machine: String,
+    pub path: PathBuf,
+}
+
+/// Counts from `merge`
+#[derive(Debug, Default)]
+pub struct MergeStats {
+    pub commits: usize,
+    pub timings: usize,
+    /// Timings found in more than one source
+    pub duplicates: usize,
+    /// Commits whose notes differed between sources
+    pub note_conflicts: usize,
+}
+
+/// Merges `data` from `machine` into `into`
+///
+/// Commits are unioned, filling in details one side lacks. Differing
+/// notes are joined with `; `. Timings are tagged with `machine` unless
+/// already tagged, and dropped if an identical timing exists, whatever
+/// its machine. Fails, changing nothing, if both define a build
+/// configuration differently.
+pub fn merge(into: &mut Data, data: Data, machine: &str, stats: &mut MergeStats) -> Result<(), ConfigConflict> {
+    for (name, config) in &data.configs {
+        match into.configs.get(name) {
+            Some(existing) if existing != config => return Err(ConfigConflict(name.clone())),
+            _ => (),
+        }
+    }
+    into.configs.extend(data.configs);
+
+    for (id, commit) in data.commits {
+        match into.commits.get_mut(&id) {
+            Some(existing) => {
+                if merge_commit(existing, commit) {
+                    stats.note_conflicts += 1;
+                }
+            }
+            None => {
+                into.commits.insert(id, commit);
+                stats.commits += 1;
+            }
+        }
+    }
+
+    for commit in data.unresolved_commits {
+        if !into.unresolved_commits.iter().any(|c| c.id == commit.id) {
+            into.unresolved_commits.push(commit);
+        }
+    }
+    let commits = &into.commits;
+    into.unresolved_commits.retain(|c| !commits.contains_key(&c.id));
+
+    for (id, timings) in data.timings {
+        let existing = into.timings.entry(id).or_insert(vec![]);
+        for mut timing in timings {
+            if timing.machine.is_none() {
+                timing.machine = Some(machine.to_string());
+            }
+            if existing.iter().any(|t| t.same_build(&timing)) {
+                stats.duplicates += 1;
+                continue;
+            }
+            existing.push(timing);
+            stats.timings += 1;
+        }
+    }
+
+    for (id, events) in data.events {
+        let existing = into.events.entry(id).or_insert(vec![]);
+        for event in events {
+            if !existing.contains(&event) {
+                existing.push(event);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fills in what `commit` knows and `into` doesn't. Returns whether the
+/// notes conflicted.
+fn merge_commit(into: &mut Commit, commit: Commit) -> bool {
+    let mut conflict = false;
+    match (&mut into.note, commit.note) {
+        (Some(ref mut a), Some(ref b)) if a != b => {
+            eprintln!("warning: commit {} has notes '{}' and '{}'. keeping both", into.id.as_str(), a, b);
+            a.push_str("; ");
+            a.push_str(b);
+            conflict = true;
+        }
+        (note @ None, Some(b)) => {
+            *note = Some(b);
+        }
+        _ => { }
+    }
+
+    // Commits resolved by older versions lack these
+    if into.author.is_none() {
+        into.author = commit.author;
+        into.author_date = commit.author_date;
+    }
+    if into.subject.is_none() {
+        into.subject = commit.subject;
+    }
+    if into.parents.is_empty() {
+        into.parents = commit.parents;
+    }
+    for tag in commit.tags {
+        if !into.tags.contains(&tag) {
+            into.tags.push(tag);
+        }
+    }
+
+    conflict
+}
+
+impl FromStr for Source {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Source, Error> {
+        let (machine, path) = match s.find('=') {
+            Some(i) => (s[..i].to_string(), Path::new(&s[i + 1..])),
+            None => {
+                let path = Path::new(s);
+                let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| Error(s.to_string()))?;
+                (stem.to_string(), path)
+            }
+        };
+        if machine.is_empty() {
+            return Err(Error(s.to_string()));
+        }
+        Ok(Source {
+            machine,
+            path: path.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "can't name a machine for '{}'. write it as NAME=PATH", _0)]
+pub struct Error(String);
+
+impl StdError for Error { }
+
+#[derive(Debug, Display)]
+#[display(fmt = "build configuration '{}' is defined differently in two databases. rename it in one of them", _0)]
+pub struct ConfigConflict(pub String);
+
+impl StdError for ConfigConflict { }
@@ -0,0 +1,376 @@
+//! Upgrades database files written by older versions of maptime.
+//!
+//! Migrations work on the raw JSON, since old files may not deserialize
+//! as the current `Data`. Each one moves the file up a single version.
+
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::error::Error as StdError;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::data::VERSION;
+
+struct Migration {
+    /// The version migrated from
+    from: u32,
+    /// Applies the migration, returning a description of each change
+    apply: fn(&mut Map<String, Value>) -> Vec<String>,
+}
+
+/// A field's name and its empty value
+type Field = (&'static str, fn() -> Value);
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { from: 0, apply: fill_missing_fields },
+    Migration { from: 1, apply: add_build_diagnostics },
+    Migration { from: 2, apply: add_build_configs },
+    Migration { from: 3, apply: add_toolchain_names },
+];
+
+/// What migrating a database does
+#[derive(Debug)]
+pub struct Report {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<String>,
+}
+
+/// Reports what migrating the database at `path` would change, without
+/// changing it. `None` if it is missing or already current.
+pub fn check(path: &Path) -> Result<Option<Report>, Error> {
+    Ok(migrate(path)?.map(|(report, _)| report))
+}
+
+/// Migrates the database at `path` in place, first copying it to
+/// `backup_path`. `None` if it is missing or already current.
+pub fn migrate_file(path: &Path) -> Result<Option<Report>, Error> {
+    let (report, value) = match migrate(path)? {
+        Some(migrated) => migrated,
+        None => return Ok(None),
+    };
+
+    let backup = backup_path(path, report.from);
+    fs::copy(path, &backup).map_err(Error::Io)?;
+
+    // Write then rename, so a crash can't leave a half-written database
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".migrating");
+    let temp = PathBuf::from(temp);
+    let json = serde_json::to_vec_pretty(&value).map_err(Error::Json)?;
+    fs::write(&temp, json).map_err(Error::Io)?;
+    fs::rename(&temp, path).map_err(Error::Io)?;
+
+    Ok(Some(report))
+}
+
+/// The version of the database at `path`. Skips over everything else
+/// rather than parsing the whole file. `None` if it is missing.
+pub fn version(path: &Path) -> Result<Option<u32>, Error> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        /// `Some(Value::Null)` for `"version": null`, unlike `Option<u64>`
+        #[serde(default, deserialize_with = "present")]
+        version: Option<Value>,
+    }
+
+    fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+        Value::deserialize(deserializer).map(Some)
+    }
+
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Io(e)),
+    };
+    let versioned: Versioned = serde_json::from_reader(io::BufReader::new(file)).map_err(Error::Json)?;
+    parse_version(versioned.version.as_ref()).map(Some)
+}
+
+/// The version field of a database, or 0 for files written before it was
+/// added
+fn parse_version(version: Option<&Value>) -> Result<u32, Error> {
+    let version = match version {
+        Some(version) => version.as_u64().ok_or(Error::BadVersion)?,
+        None => return Ok(0),
+    };
+    if version > u64::from(VERSION) {
+        return Err(Error::TooNew(version));
+    }
+    Ok(version as u32)
+}
+
+/// `maptime.json` is backed up to `maptime.json.v0.bak`
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{}.bak", version));
+    PathBuf::from(name)
+}
+
+fn migrate(path: &Path) -> Result<Option<(Report, Value)>, Error> {
+    let json = match fs::read(path) {
+        Ok(json) => json,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::Io(e)),
+    };
+    let mut value: Value = serde_json::from_slice(&json).map_err(Error::Json)?;
+    let data = value.as_object_mut().ok_or(Error::NotAnObject)?;
+
+    let from = parse_version(data.get("version"))?;
+    if from == VERSION {
+        return Ok(None);
+    }
+
+    let mut changes = vec![];
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        changes.extend((migration.apply)(data));
+        data.insert("version".to_string(), Value::from(migration.from + 1));
+    }
+
+    let report = Report {
+        from,
+        to: VERSION,
+        changes,
+    };
+    Ok(Some((report, value)))
+}
+
+/// Version 0 files may predate commit details, toolchains, machines and
+/// events. Fills them in as empty rather than relying on serde defaults.
+fn fill_missing_fields(data: &mut Map<String, Value>) -> Vec<String> {
+    let mut changes = vec![];
+
+    if !data.contains_key("events") {
+        data.insert("events".to_string(), Value::Object(Map::new()));
+        changes.push("add an empty event list".to_string());
+    }
+
+    let commit_fields: &[Field] = &[
+        ("author", || Value::Null),
+        ("author_date", || Value::Null),
+        ("subject", || Value::Null),
+        ("parents", || Value::Array(vec![])),
+        ("tags", || Value::Array(vec![])),
+    ];
+    let commits = data.get_mut("commits").and_then(Value::as_object_mut);
+    let commits = commits.into_iter().flat_map(|c| c.values_mut());
+    let count = fill(commits, commit_fields);
+    if count > 0 {
+        changes.push(format!("leave missing author, subject, parents or tags empty on {} commits", count));
+    }
+
+    let timing_fields: &[Field] = &[
+        ("toolchain", || Value::Null),
+        ("machine", || Value::Null),
+    ];
+    let count = fill(timings(data), timing_fields);
+    if count > 0 {
+        changes.push(format!("leave missing toolchain or machine empty on {} timings", count));
+    }
+
+    changes
+}
+
+/// Version 2 records cargo's exit code, first error and log with each timing
+fn add_build_diagnostics(data: &mut Map<String, Value>) -> Vec<String> {
+    let timing_fields: &[Field] = &[
+        ("exit_code", || Value::Null),
+        ("error", || Value::Null),
+        ("log", || Value::Null),
+    ];
+    let count = fill(timings(data), timing_fields);
+    if count > 0 {
+        vec![format!("leave exit code, error and log empty on {} timings", count)]
+    } else {
+        vec![]
+    }
+}
+
+/// Version 3 records the build configuration of each timing. Existing
+/// timings used the default.
+fn add_build_configs(data: &mut Map<String, Value>) -> Vec<String> {
+    let mut changes = vec![];
+    if !data.contains_key("configs") {
+        data.insert("configs".to_string(), Value::Object(Map::new()));
+        changes.push("add an empty build configuration list".to_string());
+    }
+    let count = fill(timings(data), &[("config", || Value::Null)]);
+    if count > 0 {
+        changes.push(format!("mark {} timings as built with the default configuration", count));
+    }
+    changes
+}
+
+/// Version 4 records the toolchain chosen with `--toolchain`. Existing
+/// timings used the project's own.
+fn add_toolchain_names(data: &mut Map<String, Value>) -> Vec<String> {
+    let count = fill(timings(data), &[("toolchain_name", || Value::Null)]);
+    if count > 0 {
+        vec![format!("mark {} timings as built with the project's toolchain", count)]
+    } else {
+        vec![]
+    }
+}
+
+/// Every timing object
+fn timings(data: &mut Map<String, Value>) -> impl Iterator<Item = &mut Value> {
+    let timings = data.get_mut("timings").and_then(Value::as_object_mut);
+    timings.into_iter().flat_map(|t| t.values_mut()).filter_map(Value::as_array_mut).flatten()
+}
+
+/// Adds missing fields to each object. Returns how many objects changed.
+fn fill<'a>(objects: impl Iterator<Item = &'a mut Value>, fields: &[Field]) -> usize {
+    let mut count = 0;
+    for object in objects.filter_map(Value::as_object_mut) {
+        let mut changed = false;
+        for &(field, empty) in fields {
+            if !object.contains_key(field) {
+                object.insert(field.to_string(), empty());
+                changed = true;
+            }
+        }
+        if changed {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "reading or writing database")]
+    Io(io::Error),
+    #[display(fmt = "database is not valid json")]
+    Json(serde_json::Error),
+    #[display(fmt = "database is not a json object")]
+    NotAnObject,
+    #[display(fmt = "database version is not a number")]
+    BadVersion,
+    #[display(fmt = "database version {} is newer than this maptime supports ({}). upgrade maptime", _0, VERSION)]
+    TooNew(u64),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            Error::NotAnObject => None,
+            Error::BadVersion => None,
+            Error::TooNew(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::testing::TempDir;
+
+    const COMMIT: &str = "67a32593bf76464ff650f7b116af403d7f4613c9";
+
+    /// A database as written before the schema was versioned
+    fn v0_json() -> String {
+        format!(r#"{{
+            "unresolved_commits": [],
+            "commits": {{"{id}": {{"id": "{id}", "date": "2019-10-01T00:00:00Z", "note": null}}}},
+            "timings": {{"{id}": [{{"profile": "Dev", "rebuild_type": "Full",
+                "start": "2019-10-01T00:00:00Z", "duration": {{"secs": 1, "nanos": 0}},
+                "result": "Success"}}]}}
+        }}"#, id = COMMIT)
+    }
+
+    /// A database holding `json`, in a directory removed with its backups
+    fn temp_db(name: &str, json: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new(name);
+        let path = dir.join("maptime.json");
+        fs::write(&path, json).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn migrates_v0_to_current() {
+        let (_dir, db) = temp_db("v0", &v0_json());
+
+        let report = migrate_file(&db).unwrap().unwrap();
+        assert_eq!(report.from, 0);
+        assert_eq!(report.to, VERSION);
+        assert!(!report.changes.is_empty());
+
+        let data: Data = serde_json::from_slice(&fs::read(&db).unwrap()).unwrap();
+        assert_eq!(data.version, VERSION);
+        let value: Value = serde_json::from_slice(&fs::read(&db).unwrap()).unwrap();
+        let timing = &value["timings"][COMMIT][0];
+        for field in &["toolchain", "machine", "exit_code", "error", "log", "config", "toolchain_name"] {
+            assert_eq!(timing[field], Value::Null, "{}", field);
+        }
+        assert_eq!(value["commits"][COMMIT]["parents"], Value::Array(vec![]));
+
+        let backup = fs::read_to_string(backup_path(&db, 0)).unwrap();
+        assert_eq!(backup, v0_json());
+    }
+
+    #[test]
+    fn check_leaves_file_alone() {
+        let (_dir, db) = temp_db("check", &v0_json());
+
+        let report = check(&db).unwrap().unwrap();
+        assert_eq!(report.from, 0);
+        assert_eq!(fs::read_to_string(&db).unwrap(), v0_json());
+        assert!(!backup_path(&db, 0).exists());
+    }
+
+    #[test]
+    fn current_file_is_not_migrated() {
+        let (_dir, db) = temp_db("current", &v0_json());
+        migrate_file(&db).unwrap();
+        let migrated = fs::read(&db).unwrap();
+
+        assert!(migrate_file(&db).unwrap().is_none());
+        assert_eq!(fs::read(&db).unwrap(), migrated);
+    }
+
+    #[test]
+    fn reads_version() {
+        let (dir, db) = temp_db("version", &v0_json());
+        assert_eq!(version(&db).unwrap(), Some(0));
+
+        migrate_file(&db).unwrap();
+        assert_eq!(version(&db).unwrap(), Some(VERSION));
+
+        assert_eq!(version(&dir.join("missing.json")).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let json = format!(r#"{{"version": {}, "unresolved_commits": [], "commits": {{}}, "timings": {{}}}}"#, VERSION + 1);
+        let (_dir, db) = temp_db("too-new", &json);
+
+        match version(&db) {
+            Err(Error::TooNew(v)) => assert_eq!(v, u64::from(VERSION + 1)),
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+        match migrate_file(&db) {
+            Err(Error::TooNew(_)) => {}
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&db).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_null_version() {
+        let json = r#"{"version": null, "unresolved_commits": [], "commits": {}, "timings": {}}"#;
+        let (_dir, db) = temp_db("null-version", json);
+
+        match version(&db) {
+            Err(Error::BadVersion) => {}
+            other => panic!("expected BadVersion, got {:?}", other),
+        }
+        match migrate_file(&db) {
+            Err(Error::BadVersion) => {}
+            other => panic!("expected BadVersion, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&db).unwrap(), json);
+    }
+}
@@ -0,0 +1,199 @@
+use plotters::prelude::*;
+use plotters::coord::Shift;
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+use crate::pdf::PdfBackend;
+use crate::plot::{self, PlotData, PlotOptions, Renderer, Format, YAxis};
+use crate::report;
+
+/// Draws plots with plotters, without any external program
+pub struct NativeRenderer {
+    pub format: Format,
+}
+
+impl Renderer for NativeRenderer {
+    fn render(&self, data: &PlotData, opts: &PlotOptions, file: &Path) -> Result<(), plot::Error> {
+        let size = (opts.width, opts.height);
+        match self.format {
+            Format::Svg => {
+                let mut svg = String::new();
+                draw(SVGBackend::with_string(&mut svg, size).into_drawing_area(), data, opts)?;
+                if !opts.no_labels {
+                    svg = add_titles(&svg, data);
+                }
+                fs::write(file, svg).map_err(Error::Io)?;
+            }
+            Format::Png => {
+                draw(BitMapBackend::new(file, size).into_drawing_area(), data, opts)?;
+            }
+            Format::Pdf => {
+                draw(PdfBackend::new(file, size).into_drawing_area(), data, opts)?;
+            }
+            Format::Html => {
+                return Err(plot::Error::UnsupportedFormat(plot::Backend::Native, self.format));
+            }
+        }
+
+        eprintln!("plot in {}", file.display());
+
+        Ok(())
+    }
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, data: &PlotData, opts: &PlotOptions) -> Result<(), Error> {
+    let x_axis = data.x_axis;
+    let y_axis = data.y_axis;
+    let xs: Vec<i64> = data.series.iter().flat_map(|s| s.values.iter().map(|e| x_axis.x(e))).collect();
+
+    if xs.is_empty() {
+        return Err(Error::NoData);
+    }
+
+    let x_min = *xs.iter().min().expect("xs");
+    let x_max = *xs.iter().max().expect("xs");
+    // Pad a single commit so the range isn't empty
+    let x_range = if x_min == x_max { x_min - 1 .. x_max + 1 } else { x_min .. x_max };
+    let (y_min, y_max) = data.y_range(opts.log_scale);
+
+    let commits: BTreeMap<i64, &str> = data.commits().into_iter().map(|e| (e.index as i64, e.commit.short_id())).collect();
+    let x_formatter = |x: &i64| {
+        if x_axis.is_time() {
+            Utc.timestamp(*x, 0).format("%Y-%m-%d").to_string()
+        } else {
+            commits.get(x).map(|id| id.to_string()).unwrap_or_default()
+        }
+    };
+    let y_formatter = |y: &f64| format!("{:.1}{}", y, y_axis.unit());
+    let x_labels = if x_axis.is_time() { 6 } else { commits.len().min(10) };
+
+    root.fill(&WHITE).map_err(draw_error)?;
+
+    let mut builder = ChartBuilder::on(&root);
+    builder
+        .caption(&opts.title, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60);
+
+    // Log and linear charts have different coordinate types, so the
+    // rest of the drawing is shared through a macro
+    macro_rules! draw_chart {
+        ($chart:expr) => {{
+            let mut chart = $chart.map_err(draw_error)?;
+
+            chart.configure_mesh()
+                .x_desc(x_axis.label())
+                .y_desc(y_axis.label())
+                .x_labels(x_labels)
+                .x_label_formatter(&x_formatter)
+                .y_label_formatter(&y_formatter)
+                .draw()
+                .map_err(draw_error)?;
+
+            for (i, series) in data.series.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                let line = series.values.iter().map(|e| (x_axis.x(e), series.y(e)));
+
+                chart.draw_series(LineSeries::new(line, &color))
+                    .map_err(draw_error)?
+                    .label(series.caption())
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+
+                if !opts.no_labels {
+                    let marks = series.values.iter().map(|e| {
+                        Circle::new((x_axis.x(e), series.y(e)), 2, color.filled())
+                    });
+                    chart.draw_series(marks).map_err(draw_error)?;
+                }
+            }
+
+            if y_axis == YAxis::Percent {
+                let line = vec![(x_min, 100.0), (x_max, 100.0)];
+                chart.draw_series(DashedLineSeries::new(line, 4, 3, BLACK.into()))
+                    .map_err(draw_error)?;
+            }
+
+            for marker in &data.markers {
+                let (r, g, b) = marker.kind.rgb();
+                let color = RGBColor(r, g, b);
+                let line = vec![(marker.x, y_min), (marker.x, y_max)];
+                chart.draw_series(DashedLineSeries::new(line, 6, 4, color.into()))
+                    .map_err(draw_error)?;
+                let label = EmptyElement::at((marker.x, y_max))
+                    + Text::new(marker.label.clone(), (3, 3), ("sans-serif", 11).into_font().color(&color));
+                chart.draw_series(std::iter::once(label)).map_err(draw_error)?;
+            }
+
+            chart.configure_series_labels()
+                .background_style(&WHITE.mix(0.8))
+                .border_style(&BLACK)
+                .draw()
+                .map_err(draw_error)?;
+        }}
+    }
+
+    if opts.log_scale {
+        draw_chart!(builder.build_cartesian_2d(x_range, (y_min .. y_max).log_scale()));
+    } else {
+        draw_chart!(builder.build_cartesian_2d(x_range, y_min .. y_max));
+    }
+
+    root.present().map_err(draw_error)?;
+
+    Ok(())
+}
+
+/// Gives each point's circle a `<title>`, which viewers show on hover like
+/// gnuplot's hypertext labels. plotters writes one circle per line, in the
+/// order the points were drawn.
+fn add_titles(svg: &str, data: &PlotData) -> String {
+    let titles: Vec<String> = data.series.iter()
+        .flat_map(|s| s.values.iter().map(|e| e.hover_text(data.x_axis)))
+        .collect();
+    let circles = svg.lines().filter(|l| l.starts_with("<circle")).count();
+    if circles != titles.len() {
+        eprintln!("couldn't match points to commits. not adding hover text");
+        return svg.to_string();
+    }
+
+    let mut titles = titles.iter();
+    let mut out = String::with_capacity(svg.len());
+    for line in svg.lines() {
+        match (line.strip_suffix("/>"), line.starts_with("<circle")) {
+            (Some(circle), true) => {
+                let title = titles.next().expect("counted");
+                out.push_str(&format!("{}><title>{}</title></circle>", circle, report::escape_html(title)));
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn draw_error<E: StdError + Send + Sync>(e: DrawingAreaErrorKind<E>) -> Error {
+    Error::Draw(e.to_string())
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "no timings to plot")]
+    NoData,
+    #[display(fmt = "drawing plot: {}", _0)]
+    Draw(String),
+    #[display(fmt = "writing plot")]
+    Io(std::io::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::NoData => None,
+            Error::Draw(_) => None,
+            Error::Io(ref e) => Some(e),
+        }
+    }
+}
@@ -4,7 +4,13 @@ use std::convert::TryFrom;
 use std::fmt::{self, Display, Formatter};
 use std::error::Error as StdError;
 use crate::commit_list::CommitInput;
+use crate::plot::{Backend, XAxis, PlotOptions, SeriesKey};
+use crate::output::OutputFormat;
+use crate::merge::Source;
+use crate::data::{Profile, RebuildType};
 use std::str::FromStr;
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, Utc};
 use structopt::StructOpt;
 
 /// The command the program will run along with global options
@@ -28,14 +34,237 @@ pub enum Command {
     RunAll,
     CatchUp,
     FillGaps,
-    DumpResults,
+    DumpResults {
+        /// Only this commit or tag
+        #[structopt(long)]
+        commit: Option<String>,
+        /// Only this series, like dev+full
+        #[structopt(long)]
+        series: Option<SeriesKey>,
+        /// Only builds started on or after this date, like 2019-10-01
+        #[structopt(long)]
+        since: Option<Date>,
+        /// Only builds started before this date
+        #[structopt(long)]
+        until: Option<Date>,
+    },
+    /// List failed and timed out builds with their exit code, first error and log
+    Failures,
     Plot {
         #[structopt(long, default_value = "maptime.svg")]
         file: PathBuf,
-        #[structopt(long)]
-        no_labels: bool,
+        /// Either native or gnuplot
+        #[structopt(long, default_value = "native")]
+        backend: Backend,
+        /// One of committer-date, author-date, index or topo
+        #[structopt(long, default_value = "committer-date")]
+        x_axis: XAxis,
+        #[structopt(flatten)]
+        plot_opts: PlotOptions,
     },
     Bisect,
+    /// Find the toolchain that changed a commit's build time. Orders
+    /// toolchains by their compiler's commit date, times some, then
+    /// bisects the biggest change. Nothing is recorded
+    BisectToolchains {
+        /// The commit or tag to build with every toolchain
+        #[structopt(long, default_value = "HEAD")]
+        commit: String,
+        /// Use each toolchain in this directory, like a directory of rustc
+        /// builds. Otherwise uses `--toolchain`, or every toolchain rustup
+        /// has installed
+        #[structopt(long)]
+        toolchain_dir: Option<PathBuf>,
+        /// Toolchains timed before bisecting, evenly spaced from the first
+        /// to the last
+        #[structopt(long, default_value = "2")]
+        sweep: usize,
+        /// Plot the timed toolchains to this file. `--series` also limits
+        /// the series bisected. `--baseline` doesn't apply
+        #[structopt(long)]
+        plot: Option<PathBuf>,
+        /// Either native or gnuplot
+        #[structopt(long, default_value = "native")]
+        backend: Backend,
+        #[structopt(flatten)]
+        plot_opts: PlotOptions,
+    },
+    /// Write an interactive HTML report
+    Report {
+        #[structopt(long, default_value = "maptime.html")]
+        html: PathBuf,
+        #[structopt(long, default_value = "build times")]
+        title: String,
+        /// A commit URL with {sha} in place of the commit ID
+        #[structopt(long)]
+        commit_url: Option<String>,
+        /// Percent slowdown between commits reported as a regression
+        #[structopt(long, default_value = "5")]
+        threshold: f64,
+        /// One of committer-date, author-date, index or topo
+        #[structopt(long, default_value = "committer-date")]
+        x_axis: XAxis,
+        /// Mark commits with tags matching this pattern, like `v*`
+        #[structopt(long)]
+        tags: Option<String>,
+        /// Don't mark toolchain upgrades or events added with `maptime annotate`
+        #[structopt(long)]
+        no_events: bool,
+        /// Chart each machine's timings as separate series
+        #[structopt(long)]
+        by_machine: bool,
+    },
+    /// Print the median of each series per commit, with changes between commits
+    Table {
+        /// One of committer-date, author-date, index or topo
+        #[structopt(long, default_value = "committer-date")]
+        x_axis: XAxis,
+        /// A series to show, like dev+full. May be repeated. Defaults to all
+        #[structopt(long = "series")]
+        series: Vec<SeriesKey>,
+        /// Show each machine's timings as separate series
+        #[structopt(long)]
+        by_machine: bool,
+    },
+    /// Compare the build times of two commits, like before merging a PR
+    Compare {
+        /// The base commit or tag
+        before: String,
+        /// The commit or tag compared against the base
+        after: String,
+        /// First build either commit until every configuration has this many samples
+        #[structopt(long)]
+        min_samples: Option<u32>,
+    },
+    /// Time uncommitted changes, a patch or a branch against a base commit,
+    /// without recording anything
+    Try {
+        /// The commit, tag or branch to compare against
+        #[structopt(long, default_value = "HEAD")]
+        base: String,
+        /// Time this patch applied to the base instead of the working tree
+        #[structopt(long, conflicts_with = "branch")]
+        patch: Option<PathBuf>,
+        /// Time this branch or commit instead of the working tree
+        #[structopt(long)]
+        branch: Option<String>,
+        /// Builds of each side, interleaved
+        #[structopt(long, default_value = "3")]
+        runs: u32,
+    },
+    /// Time HEAD against a base commit for CI. Exits with 2 if any series
+    /// regresses past its budget
+    Check {
+        /// The commit, tag or branch to compare HEAD against
+        #[structopt(long)]
+        base: String,
+        /// Slowdown allowed for series without a budget in the config, like 5%
+        #[structopt(long)]
+        max_regression: Option<Percent>,
+        /// Compare against the base's timings in the database instead of building it
+        #[structopt(long)]
+        stored: bool,
+        /// Builds of each side, interleaved
+        #[structopt(long, default_value = "3")]
+        runs: u32,
+        /// Where to write the result as JSON
+        #[structopt(long, default_value = "maptime-check.json")]
+        output: PathBuf,
+    },
+    /// Write every timing to a CSV file, one row per build
+    Export {
+        #[structopt(long)]
+        csv: PathBuf,
+    },
+    /// Merge timings from files written by `maptime export`
+    Import {
+        #[structopt(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Merge databases from several machines, tagging each timing with its
+    /// machine
+    Merge {
+        /// Databases written NAME=PATH, or PATH to name the machine after
+        /// the file stem
+        #[structopt(required = true)]
+        sources: Vec<Source>,
+        /// A `.sqlite` or `.db` file for SQLite, else JSON. Must not exist
+        #[structopt(short, long)]
+        output: PathBuf,
+    },
+    /// Upgrade the database to the current schema. Happens automatically
+    /// on load, with a backup
+    Migrate {
+        /// Only report what would change
+        #[structopt(long)]
+        check: bool,
+    },
+    /// Copy the database to another file, converting between JSON and
+    /// SQLite by extension
+    Convert {
+        /// A `.sqlite` or `.db` file for SQLite, else JSON. Must not exist
+        output: PathBuf,
+    },
+    /// Remove a commit with its timings and events
+    RmCommit {
+        /// A commit ID, unique prefix or tag
+        commit: String,
+        /// Only show what would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Remove old timings or timings from an environment
+    Prune {
+        /// Remove builds started before this date, like 2019-10-01
+        #[structopt(long)]
+        before: Option<Date>,
+        /// Remove builds whose toolchain or machine matches this pattern,
+        /// like `rustc 1.38*`
+        #[structopt(long)]
+        env: Option<String>,
+        /// Only show what would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Remove a commit's timings, or some of them
+    RmTimings {
+        /// A commit ID, unique prefix or tag
+        #[structopt(long)]
+        commit: Option<String>,
+        /// dev or release
+        #[structopt(long)]
+        profile: Option<Profile>,
+        /// full, partial, workspace, noop or deps
+        #[structopt(long)]
+        rebuild_type: Option<RebuildType>,
+        /// A build configuration, or `default` for builds without one
+        #[structopt(long = "build-config")]
+        config: Option<String>,
+        /// A toolchain by the name it was given with `--toolchain`
+        #[structopt(long = "toolchain")]
+        toolchain_name: Option<String>,
+        /// Only failed or timed out builds
+        #[structopt(long)]
+        failed: bool,
+        /// Only show what would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Set a commit's note. An empty note clears it
+    EditNote {
+        /// A commit ID, unique prefix or tag
+        commit: String,
+        note: String,
+        /// Only show what would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Record an event at a commit, like "switched to lld", drawn as a plot marker
+    Annotate {
+        /// A commit or tag
+        commit: String,
+        event: String,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -46,6 +275,186 @@ pub struct GlobalOptions {
     pub repo_path: PathBuf,
     #[structopt(long)]
     pub project_path: Option<PathBuf>,
+    #[structopt(long, default_value = "maptime.toml")]
+    pub config: PathBuf,
+    #[structopt(flatten)]
+    pub build: BuildOptions,
+    /// Only use timings from this machine, as tagged by `maptime merge`
+    #[structopt(long)]
+    pub machine: Option<String>,
+    /// Output of reporting, editing and migrating commands: text, json or
+    /// csv. Logging always goes to stderr
+    #[structopt(long, default_value = "text", global = true)]
+    pub format: OutputFormat,
+}
+
+/// How builds are run
+#[derive(Debug, StructOpt)]
+pub struct BuildOptions {
+    /// Builds to time after each clean, from full, partial, workspace
+    /// (only the workspace's crates cleaned), noop (nothing changed) and
+    /// deps (only dependencies)
+    #[structopt(long = "rebuild", use_delimiter = true, number_of_values = 1, default_value = "full,partial")]
+    pub rebuild_types: Vec<RebuildType>,
+    /// Build configurations to time, from `[configs]` in the config file.
+    /// `default` has no overrides
+    #[structopt(long = "build-config", use_delimiter = true, number_of_values = 1, default_value = "default")]
+    pub configs: Vec<String>,
+    /// Toolchains to time each commit with, like `stable,1.70.0` or
+    /// `local=/opt/rust` for one outside rustup. A directory without
+    /// `bin/cargo`, like a rustc stage1 build, uses the cargo on the PATH.
+    /// Defaults to the one the project selects
+    #[structopt(long = "toolchain", use_delimiter = true, number_of_values = 1)]
+    pub toolchains: Vec<Toolchain>,
+    /// Where cargo's output is saved, one file per build
+    #[structopt(long, default_value = "maptime-logs")]
+    pub log_dir: PathBuf,
+    /// Kill builds running longer than this, like 90s, 30m or 2h. Minutes
+    /// if there's no unit
+    #[structopt(long)]
+    pub timeout: Option<Timeout>,
+    /// Times to retry a failed setup step, like `cargo fetch` or `cargo
+    /// metadata`, waiting 5s then twice as long each time
+    #[structopt(long, default_value = "3")]
+    pub retries: u32,
+    /// Pass `--offline` to cargo, using only crates already downloaded or
+    /// vendored. Set up a vendor directory or registry mirror with source
+    /// replacement in `.cargo/config`
+    #[structopt(long)]
+    pub offline: bool,
+    /// Pass `--frozen` to cargo, like `--offline` but also requiring
+    /// Cargo.lock to be up to date
+    #[structopt(long)]
+    pub frozen: bool,
+}
+
+impl BuildOptions {
+    /// The toolchains to build with. `None` is the one the project selects
+    pub fn toolchains(&self) -> Vec<Option<&Toolchain>> {
+        if self.toolchains.is_empty() {
+            vec![None]
+        } else {
+            self.toolchains.iter().map(Some).collect()
+        }
+    }
+
+    /// The toolchain named `name`, either from `--toolchain` or as a
+    /// rustup toolchain
+    pub fn toolchain(&self, name: &str) -> Toolchain {
+        self.toolchains.iter().find(|t| t.name == name).cloned().unwrap_or_else(|| Toolchain {
+            name: name.to_string(),
+            path: None,
+        })
+    }
+}
+
+/// A toolchain to build with, written as a rustup toolchain like
+/// `nightly-2024-01-01`, or `NAME=PATH` for one installed elsewhere. A
+/// bare path is named after its directory.
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    pub name: String,
+    /// The directory holding `bin/rustc`, and `bin/cargo` unless cargo
+    /// comes from the PATH. `None` for rustup toolchains
+    pub path: Option<PathBuf>,
+}
+
+impl FromStr for Toolchain {
+    type Err = ToolchainParseError;
+
+    fn from_str(s: &str) -> Result<Toolchain, ToolchainParseError> {
+        let (name, path) = match s.find('=') {
+            Some(i) => (&s[..i], Some(Path::new(&s[i + 1..]))),
+            None if s.contains('/') => {
+                let path = Path::new(s);
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                (name, Some(path))
+            }
+            None => (s, None),
+        };
+        // Series names are written `dev+full:NAME@machine`
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err(ToolchainParseError(s.to_string()));
+        }
+        Ok(Toolchain {
+            name: name.to_string(),
+            path: path.map(Path::to_owned),
+        })
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "bad toolchain '{}'. expected a rustup toolchain like 1.70.0, or NAME=PATH with a name of letters, digits, '.', '-' and '_'", _0)]
+pub struct ToolchainParseError(String);
+
+impl StdError for ToolchainParseError { }
+
+/// A build timeout, like `90s`, `30m`, `2h` or `30` for minutes
+#[derive(Debug, Copy, Clone)]
+pub struct Timeout(pub Duration);
+
+impl FromStr for Timeout {
+    type Err = TimeoutParseError;
+
+    fn from_str(s: &str) -> Result<Timeout, TimeoutParseError> {
+        let s = s.trim();
+        let (num, secs) = if s.ends_with('s') {
+            (&s[..s.len() - 1], 1)
+        } else if s.ends_with('m') {
+            (&s[..s.len() - 1], 60)
+        } else if s.ends_with('h') {
+            (&s[..s.len() - 1], 60 * 60)
+        } else {
+            (s, 60)
+        };
+        match num.parse::<u64>().ok().and_then(|num| num.checked_mul(secs)) {
+            Some(secs) if secs > 0 => Ok(Timeout(Duration::from_secs(secs))),
+            _ => Err(TimeoutParseError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "bad timeout '{}'. expected a number with s, m or h, like 30m", _0)]
+pub struct TimeoutParseError(String);
+
+impl StdError for TimeoutParseError { }
+
+/// A percentage of zero or more, written with or without a `%`, like `5%`
+#[derive(Debug, Copy, Clone)]
+pub struct Percent(pub f64);
+
+impl FromStr for Percent {
+    type Err = PercentParseError;
+
+    fn from_str(s: &str) -> Result<Percent, PercentParseError> {
+        match s.trim().trim_end_matches('%').parse::<f64>() {
+            Ok(percent) if percent.is_finite() && percent >= 0.0 => Ok(Percent(percent)),
+            _ => Err(PercentParseError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "bad percentage '{}'. expected a number of zero or more, like 5%", _0)]
+pub struct PercentParseError(String);
+
+impl StdError for PercentParseError { }
+
+/// A date, either RFC 3339 or like `2019-10-01` for midnight UTC
+#[derive(Debug, Copy, Clone)]
+pub struct Date(pub DateTime<Utc>);
+
+impl FromStr for Date {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Date, Self::Err> {
+        match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(date) => Ok(Date(DateTime::from_utc(date.and_hms(0, 0, 0), Utc))),
+            Err(_) => DateTime::parse_from_rfc3339(s).map(|d| Date(d.with_timezone(&Utc))),
+        }
+    }
 }
 
 impl GlobalOptions {
@@ -0,0 +1,385 @@
+//! The machine-readable output of `--format json` and `--format csv`.
+//!
+//! Every row type here is a stable, flat structure: field names are the
+//! JSON keys and CSV headers, dates are RFC 3339, and lists are joined with
+//! spaces. JSON output is an array of rows, CSV output one line per row.
+
+use std::error::Error as StdError;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use crate::analysis::{Comparison, Failure};
+use crate::data::{Commit, Timing, VERSION};
+use crate::migrate::{backup_path, Report};
+use crate::plot::{PlotData, Series, Entry};
+use crate::stats;
+use crate::toolchain_bisect::Bisection;
+
+/// How reporting commands print their results to stdout
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// The formats of `write_rows`, which has no text format
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum RowFormat {
+    Json,
+    Csv,
+}
+
+/// A commit from `list-commits`
+#[derive(Serialize)]
+pub struct CommitRow<'a> {
+    pub id: &'a str,
+    /// The committer date
+    pub date: String,
+    pub author: Option<&'a str>,
+    pub author_date: Option<String>,
+    pub subject: Option<&'a str>,
+    pub note: Option<&'a str>,
+    /// Space-separated
+    pub tags: String,
+    /// Space-separated commit IDs
+    pub parents: String,
+}
+
+/// A build from `dump-results`
+#[derive(Serialize)]
+pub struct TimingRow<'a> {
+    pub commit: &'a str,
+    pub profile: &'a str,
+    pub rebuild_type: &'a str,
+    /// The build configuration, empty for the default
+    pub config: Option<&'a str>,
+    /// The toolchain chosen with `--toolchain`, empty for the project's own
+    pub toolchain_name: Option<&'a str>,
+    pub start: String,
+    pub duration_ms: f64,
+    /// `success`, `failure`, `timeout` or `setup-failure`
+    pub result: &'a str,
+    /// The `rustc -V` of the build, if recorded
+    pub toolchain: Option<&'a str>,
+    /// The machine that ran the build, if merged from another database
+    pub machine: Option<&'a str>,
+    /// cargo's exit code
+    pub exit_code: Option<i32>,
+    /// The first compiler error of a failed build
+    pub error: Option<&'a str>,
+    /// The file holding cargo's output
+    pub log: Option<String>,
+}
+
+/// A configuration that failed to build at a commit, from `failures`
+#[derive(Serialize)]
+pub struct FailureRow<'a> {
+    pub commit: &'a str,
+    /// Like `dev+full`
+    pub series: String,
+    pub failures: usize,
+    pub builds: usize,
+    /// The latest failure's result, `failure`, `timeout` or `setup-failure`
+    pub result: &'a str,
+    /// The latest failure's exit code
+    pub exit_code: Option<i32>,
+    /// The latest failure's first compiler error
+    pub error: Option<&'a str>,
+    /// The latest failure's output
+    pub log: Option<String>,
+}
+
+/// A configuration's medians at two commits, from `compare` and `try`
+#[derive(Serialize)]
+pub struct ComparisonRow {
+    /// Like `dev+full`
+    pub series: String,
+    pub verdict: String,
+    pub before_median_secs: Option<f64>,
+    pub after_median_secs: Option<f64>,
+    pub change_pct: Option<f64>,
+    pub before_samples: usize,
+    pub after_samples: usize,
+}
+
+/// A plotted point, from `plot` and `table`
+#[derive(Serialize)]
+pub struct PointRow<'a> {
+    pub series: String,
+    pub commit: &'a str,
+    /// The committer or author date, as on the x-axis
+    pub date: String,
+    pub note: Option<&'a str>,
+    pub mean_secs: f64,
+    pub median_secs: f64,
+    pub stddev_secs: f64,
+    pub samples: usize,
+    /// The plotted value, in seconds or percent of the series' baseline
+    pub y: f64,
+    /// Percent change of the median from the series' previous commit
+    pub delta_pct: Option<f64>,
+}
+
+/// The toolchains either side of a change, from `bisect-toolchains`
+#[derive(Serialize)]
+pub struct ToolchainChangeRow<'a> {
+    /// Like `dev+full`
+    pub series: String,
+    pub old_toolchain: &'a str,
+    /// The rust-lang/rust commit the old toolchain was built from
+    pub old_commit: &'a str,
+    pub old_secs: f64,
+    pub new_toolchain: &'a str,
+    pub new_commit: &'a str,
+    pub new_secs: f64,
+}
+
+/// A database's schema version, from `migrate`
+#[derive(Serialize)]
+pub struct MigrationRow {
+    pub file: String,
+    /// The version found, empty if there is no database
+    pub from: Option<u32>,
+    pub to: u32,
+    /// Whether the database was upgraded. False with `--check`
+    pub migrated: bool,
+    /// What upgrading changes, separated by `; `
+    pub changes: String,
+    /// The copy of the database from before upgrading
+    pub backup: Option<String>,
+}
+
+/// A change to the database, from `rm-commit`, `prune`, `rm-timings` and
+/// `edit-note`
+#[derive(Serialize)]
+pub struct ChangeRow<'a> {
+    pub change: &'a str,
+    /// Whether the change was left unwritten with `--dry-run`
+    pub dry_run: bool,
+}
+
+impl<'a> CommitRow<'a> {
+    pub fn new(commit: &'a Commit) -> CommitRow<'a> {
+        CommitRow {
+            id: commit.id.as_str(),
+            date: commit.date.to_rfc3339(),
+            author: commit.author.as_ref().map(String::as_str),
+            author_date: commit.author_date.map(|d| d.to_rfc3339()),
+            subject: commit.subject.as_ref().map(String::as_str),
+            note: commit.note.as_ref().map(String::as_str),
+            tags: commit.tags.join(" "),
+            parents: commit.parents.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+impl<'a> TimingRow<'a> {
+    pub fn new(commit: &'a str, timing: &'a Timing) -> TimingRow<'a> {
+        TimingRow {
+            commit,
+            profile: timing.profile.as_ref(),
+            rebuild_type: timing.rebuild_type.as_ref(),
+            config: timing.config.as_ref().map(String::as_str),
+            toolchain_name: timing.toolchain_name.as_ref().map(String::as_str),
+            start: timing.start.to_rfc3339(),
+            duration_ms: timing.duration.as_secs_f64() * 1000.0,
+            result: timing.result.as_ref(),
+            toolchain: timing.toolchain.as_ref().map(String::as_str),
+            machine: timing.machine.as_ref().map(String::as_str),
+            exit_code: timing.exit_code,
+            error: timing.error.as_ref().map(String::as_str),
+            log: timing.log.as_ref().map(|l| l.display().to_string()),
+        }
+    }
+}
+
+impl<'a> FailureRow<'a> {
+    pub fn new(f: &'a Failure) -> FailureRow<'a> {
+        FailureRow {
+            commit: f.commit.as_str(),
+            series: f.key.name(),
+            failures: f.failures,
+            builds: f.builds,
+            result: f.latest.result.as_ref(),
+            exit_code: f.latest.exit_code,
+            error: f.latest.error.as_ref().map(String::as_str),
+            log: f.latest.log.as_ref().map(|l| l.display().to_string()),
+        }
+    }
+}
+
+impl ComparisonRow {
+    pub fn new(c: &Comparison) -> ComparisonRow {
+        let median = |samples: &[_]| if samples.is_empty() { None } else { Some(stats::median(samples).as_secs_f64()) };
+        let both = !c.before.is_empty() && !c.after.is_empty();
+        ComparisonRow {
+            series: c.key.name(),
+            verdict: c.verdict().as_ref().to_string(),
+            before_median_secs: median(&c.before),
+            after_median_secs: median(&c.after),
+            change_pct: if both { Some(c.change() * 100.0) } else { None },
+            before_samples: c.before.len(),
+            after_samples: c.after.len(),
+        }
+    }
+}
+
+impl<'a> ToolchainChangeRow<'a> {
+    pub fn new(b: &'a Bisection) -> ToolchainChangeRow<'a> {
+        ToolchainChangeRow {
+            series: b.key.name(),
+            old_toolchain: &b.old.toolchain.name,
+            old_commit: b.old.commit.id.as_str(),
+            old_secs: b.old_duration.as_secs_f64(),
+            new_toolchain: &b.new.toolchain.name,
+            new_commit: b.new.commit.id.as_str(),
+            new_secs: b.new_duration.as_secs_f64(),
+        }
+    }
+}
+
+impl MigrationRow {
+    /// `report` is `None` if the database is missing or current
+    pub fn new(path: &Path, report: Option<&Report>, migrated: bool) -> MigrationRow {
+        let file = path.display().to_string();
+        match report {
+            Some(report) => MigrationRow {
+                file,
+                from: Some(report.from),
+                to: report.to,
+                migrated,
+                changes: report.changes.join("; "),
+                backup: if migrated { Some(backup_path(path, report.from).display().to_string()) } else { None },
+            },
+            None => MigrationRow {
+                file,
+                from: if path.exists() { Some(VERSION) } else { None },
+                to: VERSION,
+                migrated: false,
+                changes: String::new(),
+                backup: None,
+            },
+        }
+    }
+}
+
+impl<'a> PointRow<'a> {
+    /// Rows for every point, series by series
+    pub fn all(data: &'a PlotData) -> Vec<PointRow<'a>> {
+        let mut rows = vec![];
+        for series in &data.series {
+            for (i, entry) in series.values.iter().enumerate() {
+                let prev = if i > 0 { series.values.get(i - 1) } else { None };
+                rows.push(PointRow::new(data, series, entry, prev));
+            }
+        }
+        rows
+    }
+
+    fn new(data: &PlotData, series: &Series, entry: &'a Entry, prev: Option<&Entry>) -> PointRow<'a> {
+        let median = stats::median(&entry.samples).as_secs_f64();
+        let delta_pct = prev.map(|prev| {
+            let prev = stats::median(&prev.samples).as_secs_f64();
+            (median - prev) / prev * 100.0
+        });
+        PointRow {
+            series: series.caption(),
+            commit: entry.commit.id.as_str(),
+            date: data.x_axis.date(&entry.commit).to_rfc3339(),
+            note: entry.commit.note.as_ref().map(String::as_str),
+            mean_secs: entry.duration.as_secs_f64(),
+            median_secs: median,
+            stddev_secs: entry.stddev().as_secs_f64(),
+            samples: entry.samples.len(),
+            y: series.y(entry),
+            delta_pct,
+        }
+    }
+}
+
+/// Writes a single value to stdout as JSON
+pub fn write_json<T: serde::Serialize>(value: &T) -> Result<(), Error> {
+    let stdout = io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), value).map_err(Error::Json)?;
+    println!();
+    Ok(())
+}
+
+/// Writes rows to stdout as JSON or CSV. Text output is up to each command.
+pub fn write_rows<T: serde::Serialize>(format: RowFormat, rows: &[T]) -> Result<(), Error> {
+    match format {
+        RowFormat::Json => {
+            write_json(&rows)?;
+        }
+        RowFormat::Csv => {
+            let stdout = io::stdout();
+            let mut writer = csv::Writer::from_writer(stdout.lock());
+            for row in rows {
+                writer.serialize(row).map_err(Error::Csv)?;
+            }
+            writer.flush().map_err(Error::Io)?;
+        }
+    }
+    Ok(())
+}
+
+impl OutputFormat {
+    /// How to write rows in this format, `None` for text
+    pub fn rows(self) -> Option<RowFormat> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Json => Some(RowFormat::Json),
+            OutputFormat::Csv => Some(RowFormat::Csv),
+        }
+    }
+}
+
+impl AsRef<str> for OutputFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<OutputFormat, ParseError> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(ParseError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "unknown output format '{}'. expected text, json or csv", _0)]
+pub struct ParseError(String);
+
+impl StdError for ParseError { }
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "writing json")]
+    Json(serde_json::Error),
+    #[display(fmt = "writing csv")]
+    Csv(csv::Error),
+    #[display(fmt = "writing output")]
+    Io(io::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Json(ref e) => Some(e),
+            Error::Csv(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+    FontTransform,
+};
+use plotters_backend::text_anchor::{HPos, VPos};
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A plotters backend writing a single-page vector PDF.
+///
+/// Text is set in the PDF base font Helvetica, so nothing is embedded.
+/// Transparency is approximated by blending with white.
+pub struct PdfBackend<'a> {
+    path: &'a Path,
+    size: (u32, u32),
+    content: String,
+}
+
+impl<'a> PdfBackend<'a> {
+    pub fn new(path: &'a Path, size: (u32, u32)) -> PdfBackend<'a> {
+        PdfBackend {
+            path,
+            size,
+            content: String::new(),
+        }
+    }
+
+    /// PDF's origin is the bottom left
+    fn flip(&self, (x, y): BackendCoord) -> (i32, i32) {
+        (x, self.size.1 as i32 - y)
+    }
+
+    fn stroke_style<S: BackendStyle>(&mut self, style: &S) {
+        let (r, g, b) = rgb(style.color());
+        let _ = writeln!(self.content, "{} {} {} RG {} w", r, g, b, style.stroke_width());
+    }
+
+    fn fill_style(&mut self, color: BackendColor) {
+        let (r, g, b) = rgb(color);
+        let _ = writeln!(self.content, "{} {} {} rg", r, g, b);
+    }
+
+    fn path<I: IntoIterator<Item = BackendCoord>>(&mut self, points: I) {
+        for (i, point) in points.into_iter().enumerate() {
+            let (x, y) = self.flip(point);
+            let op = if i == 0 { "m" } else { "l" };
+            let _ = writeln!(self.content, "{} {} {}", x, y, op);
+        }
+    }
+
+    fn document(&self) -> Vec<u8> {
+        let objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                     /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>",
+                    self.size.0, self.size.1),
+            format!("<< /Length {} >>\nstream\n{}endstream", self.content.len(), self.content),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+
+        let mut out = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            let _ = write!(out, "{} 0 obj\n{}\nendobj\n", i + 1, object);
+        }
+        let xref = out.len();
+        let _ = write!(out, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = write!(out, "{:010} 00000 n \n", offset);
+        }
+        let _ = write!(out, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                       objects.len() + 1, xref);
+
+        out.into_bytes()
+    }
+}
+
+impl<'a> DrawingBackend for PdfBackend<'a> {
+    type ErrorType = io::Error;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        fs::write(self.path, self.document()).map_err(DrawingErrorKind::DrawingError)
+    }
+
+    fn draw_pixel(&mut self, point: BackendCoord, color: BackendColor) -> Result<(), DrawingErrorKind<io::Error>> {
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+        let (x, y) = self.flip(point);
+        self.fill_style(color);
+        let _ = writeln!(self.content, "{} {} 1 1 re f", x, y - 1);
+        Ok(())
+    }
+
+    fn draw_line<S: BackendStyle>(&mut self, from: BackendCoord, to: BackendCoord, style: &S) -> Result<(), DrawingErrorKind<io::Error>> {
+        self.draw_path(vec![from, to], style)
+    }
+
+    fn draw_rect<S: BackendStyle>(&mut self, upper_left: BackendCoord, bottom_right: BackendCoord, style: &S, fill: bool) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+        let (x0, y0) = self.flip(upper_left);
+        let (x1, y1) = self.flip(bottom_right);
+        if fill {
+            self.fill_style(style.color());
+        } else {
+            self.stroke_style(style);
+        }
+        let _ = writeln!(self.content, "{} {} {} {} re {}", x0, y1, x1 - x0, y0 - y1, if fill { "f" } else { "S" });
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, path: I, style: &S) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+        self.stroke_style(style);
+        self.path(path);
+        self.content.push_str("S\n");
+        Ok(())
+    }
+
+    fn draw_circle<S: BackendStyle>(&mut self, center: BackendCoord, radius: u32, style: &S, fill: bool) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+        let (cx, cy) = self.flip(center);
+        let (cx, cy, r) = (cx as f64, cy as f64, radius as f64);
+        // Four Bézier quarter circles
+        let k = r * 0.5523;
+        if fill {
+            self.fill_style(style.color());
+        } else {
+            self.stroke_style(style);
+        }
+        let _ = writeln!(self.content, "{} {} m", cx + r, cy);
+        let _ = writeln!(self.content, "{} {} {} {} {} {} c", cx + r, cy + k, cx + k, cy + r, cx, cy + r);
+        let _ = writeln!(self.content, "{} {} {} {} {} {} c", cx - k, cy + r, cx - r, cy + k, cx - r, cy);
+        let _ = writeln!(self.content, "{} {} {} {} {} {} c", cx - r, cy - k, cx - k, cy - r, cx, cy - r);
+        let _ = writeln!(self.content, "{} {} {} {} {} {} c", cx + k, cy - r, cx + r, cy - k, cx + r, cy);
+        self.content.push_str(if fill { "f\n" } else { "S\n" });
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, vert: I, style: &S) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+        self.fill_style(style.color());
+        self.path(vert);
+        self.content.push_str("h f\n");
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(&mut self, text: &str, style: &TStyle, pos: BackendCoord) -> Result<(), DrawingErrorKind<io::Error>> {
+        let color = style.color();
+        if color.alpha == 0.0 {
+            return Ok(());
+        }
+
+        let (width, height) = self.estimate_text_size(text, style)?;
+        let (width, height) = (width as i32, height as i32);
+        let dx = match style.anchor().h_pos {
+            HPos::Left => 0,
+            HPos::Right => -width,
+            HPos::Center => -width / 2,
+        };
+        // Offset from the anchor down to the baseline
+        let dy = match style.anchor().v_pos {
+            VPos::Top => height,
+            VPos::Center => height / 2,
+            VPos::Bottom => 0,
+        };
+        // Rotate the offset and text along with the requested transform
+        let (ox, oy, matrix) = match style.transform() {
+            FontTransform::Rotate90 => (-dy, dx, "0 -1 1 0"),
+            FontTransform::Rotate180 => (-dx, -dy, "-1 0 0 -1"),
+            FontTransform::Rotate270 => (dy, -dx, "0 1 -1 0"),
+            _ => (dx, dy, "1 0 0 1"),
+        };
+        let (x, y) = self.flip((pos.0 + ox, pos.1 + oy));
+
+        self.fill_style(color);
+        let _ = writeln!(self.content, "BT /F1 {} Tf {} {} {} Tm ({}) Tj ET",
+                         style.size(), matrix, x, y, escape(text));
+        Ok(())
+    }
+}
+
+fn rgb(color: BackendColor) -> (f64, f64, f64) {
+    let (r, g, b) = color.rgb;
+    let blend = |c: u8| {
+        let c = c as f64 / 255.0;
+        c * color.alpha + (1.0 - color.alpha)
+    };
+    (blend(r), blend(g), blend(b))
+}
+
+/// Escapes a PDF string literal. Helvetica only covers Latin-1.
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' ..= '~' => out.push(c),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
@@ -0,0 +1,772 @@
+use crate::data::{Commit, Data};
+use crate::commit_id::CommitId;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::data::{Profile, RebuildType};
+use crate::native::NativeRenderer;
+use crate::terminal;
+use std::io;
+use crate::stats;
+use crate::analysis;
+use crate::report::{self, ReportOptions};
+
+#[derive(Clone)]
+pub struct PlotData {
+    pub series: Vec<Series>,
+    pub x_axis: XAxis,
+    pub y_axis: YAxis,
+    pub markers: Vec<Marker>,
+}
+
+#[derive(Clone)]
+pub struct Series {
+    pub key: SeriesKey,
+    /// Set when series are split by machine with `--by-machine`
+    pub machine: Option<String>,
+    pub values: Vec<Entry>,
+    /// The duration plotted as 100% when the y-axis is in percent
+    pub baseline: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub commit: Commit,
+    /// Position of the commit in the plot's commit ordering
+    pub index: usize,
+    /// The mean of `samples`
+    pub duration: Duration,
+    pub samples: Vec<Duration>,
+}
+
+/// A vertical line at a commit, explaining a step in the curves
+#[derive(Debug, Clone)]
+pub struct Marker {
+    /// Position on the x-axis, like `XAxis::x`
+    pub x: i64,
+    pub kind: MarkerKind,
+    pub label: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MarkerKind {
+    /// A git tag matching `--tags`
+    Tag,
+    /// The first commit built with a new `rustc -V`
+    Toolchain,
+    /// An event added with `maptime annotate`
+    Event,
+}
+
+/// Identifies a series by its build configuration, written like `dev+full`,
+/// `dev+full+lld` for builds with the `lld` configuration, or
+/// `dev+full:nightly` for builds with the `nightly` toolchain
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SeriesKey {
+    pub profile: Profile,
+    pub rebuild_type: RebuildType,
+    /// `None` for the default configuration
+    pub config: Option<String>,
+    /// `None` for the toolchain the project selects
+    pub toolchain_name: Option<String>,
+}
+
+/// Draws `PlotData` to a file
+pub trait Renderer {
+    fn render(&self, data: &PlotData, opts: &PlotOptions, file: &Path) -> Result<(), Error>;
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PlotOptions {
+    /// Don't mark each commit with a point showing its date, ID, author
+    /// and description on hover
+    #[structopt(long)]
+    pub no_labels: bool,
+    /// One of svg, png, pdf or html. Defaults to the file extension
+    #[structopt(long)]
+    pub image_format: Option<Format>,
+    #[structopt(long, default_value = "600")]
+    pub width: u32,
+    #[structopt(long, default_value = "400")]
+    pub height: u32,
+    #[structopt(long, default_value = "build times")]
+    pub title: String,
+    #[structopt(long)]
+    pub log_scale: bool,
+    /// Either seconds, or percent of each series' first commit
+    #[structopt(long, default_value = "seconds")]
+    pub y_axis: YAxis,
+    /// A commit or tag to plot every series as a percent of. Implies
+    /// `--y-axis percent`
+    #[structopt(long)]
+    pub baseline: Option<String>,
+    /// A series to plot, like dev+full. May be repeated. Defaults to all
+    #[structopt(long = "series")]
+    pub series: Vec<SeriesKey>,
+    /// Mark commits with tags matching this pattern, like `v*`
+    #[structopt(long)]
+    pub tags: Option<String>,
+    /// Don't mark toolchain upgrades or events added with `maptime annotate`
+    #[structopt(long)]
+    pub no_events: bool,
+    /// Draw the plot as text on stdout instead of to a file
+    #[structopt(long)]
+    pub terminal: bool,
+    /// Write one file per series if their times differ by 10x or more
+    #[structopt(long)]
+    pub split: bool,
+    /// Plot each machine's timings as separate series
+    #[structopt(long)]
+    pub by_machine: bool,
+}
+
+/// Which renderer draws the plot
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Backend {
+    /// Pure Rust, no external programs
+    Native,
+    /// Requires the `gnuplot` cargo feature and the gnuplot executable
+    Gnuplot,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Format {
+    Svg,
+    Png,
+    Pdf,
+    /// The interactive report
+    Html,
+}
+
+/// What the x-axis of the plot measures
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum XAxis {
+    CommitterDate,
+    AuthorDate,
+    /// Commits evenly spaced in committer date order
+    Index,
+    /// Commits evenly spaced in git's topological order
+    Topological,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum YAxis {
+    Seconds,
+    /// Percent of each series' baseline
+    Percent,
+}
+
+pub fn plot(data: &PlotData, file: &Path, backend: Backend, opts: &PlotOptions) -> Result<(), Error> {
+    if opts.split && data.spans_magnitudes() {
+        eprintln!("series differ by an order of magnitude. writing one plot per series");
+        for series in &data.series {
+            let mut single = data.clone();
+            single.series = vec![series.clone()];
+            let file = series_file(file, &series.caption());
+            plot_one(&single, &file, backend, opts)?;
+        }
+        Ok(())
+    } else {
+        plot_one(data, file, backend, opts)
+    }
+}
+
+fn plot_one(data: &PlotData, file: &Path, backend: Backend, opts: &PlotOptions) -> Result<(), Error> {
+    if opts.terminal {
+        let stdout = io::stdout();
+        return Ok(terminal::render(data, opts, &mut stdout.lock())?);
+    }
+
+    let format = match opts.image_format {
+        Some(format) => format,
+        None => Format::from_path(file)?,
+    };
+
+    if format == Format::Html {
+        let report_opts = ReportOptions {
+            title: &opts.title,
+            commit_url: None,
+            threshold: analysis::DEFAULT_THRESHOLD,
+        };
+        let regressions = analysis::find_regressions(data, report_opts.threshold);
+        return Ok(report::write_html(data, &regressions, &report_opts, file)?);
+    }
+
+    match backend {
+        Backend::Native => {
+            NativeRenderer { format }.render(data, opts, file)
+        }
+        #[cfg(feature = "gnuplot")]
+        Backend::Gnuplot => {
+            crate::gnuplot::GnuplotRenderer { format }.render(data, opts, file)
+        }
+        #[cfg(not(feature = "gnuplot"))]
+        Backend::Gnuplot => {
+            Err(Error::GnuplotDisabled)
+        }
+    }
+}
+
+/// The entries of one series. `machine` selects timings from one machine,
+/// where `Some(None)` is untagged timings, or from all if `None`.
+fn series_values(data: &Data, commits: &[CommitId], key: &SeriesKey, machine: Option<Option<&str>>) -> Vec<Entry> {
+    let mut values = Vec::new();
+    for (index, commit) in commits.iter().enumerate() {
+        if data.timings.contains_key(commit) {
+            let samples = match machine {
+                Some(machine) => data.machine_samples(commit, key, machine),
+                None => data.samples(commit, key),
+            };
+            if samples.is_empty() {
+                // Machines rarely time the same commits, so don't warn per machine
+                if machine.is_none() {
+                    eprintln!("warning: no timings for {}, series {}", commit.as_ref(), key.name());
+                }
+                continue;
+            }
+            let avg = stats::mean(&samples);
+            let commit = data.commits.get(commit);
+            let commit = commit.expect("commit with timing should exists");
+            let commit = commit.clone();
+            let entry = Entry {
+                commit,
+                index,
+                duration: avg,
+                samples,
+            };
+            values.push(entry);
+        } else if machine.is_none() {
+            eprintln!("warning: no timings for {}", commit.as_ref());
+        }
+    }
+    values
+}
+
+/// `maptime.svg` becomes `maptime-dev+full.svg`
+fn series_file(file: &Path, caption: &str) -> PathBuf {
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("maptime");
+    let mut name = format!("{}-{}", stem, caption);
+    if let Some(ext) = file.extension().and_then(|e| e.to_str()) {
+        name.push('.');
+        name.push_str(ext);
+    }
+    file.with_file_name(name)
+}
+
+impl PlotData {
+    /// The series of every configuration with timings, over `commits` in
+    /// x-axis order. With `by_machine`, one series per machine.
+    pub fn from_data(data: &Data, commits: &[CommitId], x_axis: XAxis, by_machine: bool) -> PlotData {
+        let mut serieses = Vec::new();
+        for key in data.series_keys() {
+            if by_machine {
+                for machine in data.machines() {
+                    let machine = machine.as_ref().map(String::as_str);
+                    serieses.push(Series {
+                        key: key.clone(),
+                        machine: Some(machine.unwrap_or("untagged").to_string()),
+                        values: series_values(data, commits, &key, Some(machine)),
+                        baseline: None,
+                    });
+                }
+            } else {
+                serieses.push(Series {
+                    key: key.clone(),
+                    machine: None,
+                    values: series_values(data, commits, &key, None),
+                    baseline: None,
+                });
+            }
+        }
+        // A machine may not have built every series
+        serieses.retain(|s| !s.values.is_empty());
+
+        PlotData {
+            series: serieses,
+            x_axis,
+            y_axis: YAxis::Seconds,
+            markers: vec![],
+        }
+    }
+
+    /// Every plotted commit once, in x-axis order
+    pub fn commits(&self) -> Vec<&Entry> {
+        let mut commits: Vec<&Entry> = self.series.iter().flat_map(|s| s.values.iter()).collect();
+        commits.sort_by_key(|e| e.index);
+        commits.dedup_by_key(|e| e.index);
+        commits
+    }
+
+    /// Up to ten evenly spaced x positions labeled by short commit ID,
+    /// for categorical axes
+    pub fn commit_ticks(&self) -> Vec<(i64, String)> {
+        let commits = self.commits();
+        let step = (commits.len() / 10).max(1);
+        commits.iter().step_by(step).map(|e| {
+            (e.index as i64, e.commit.short_id().to_string())
+        }).collect()
+    }
+
+    /// Keeps only the given series. Keeps everything if `keys` is empty.
+    pub fn select(&mut self, keys: &[SeriesKey]) {
+        if !keys.is_empty() {
+            self.series.retain(|s| keys.contains(&s.key));
+        }
+    }
+
+    /// Rescales each series as a percent of its first value
+    pub fn rebase_to_first(&mut self) {
+        for series in &mut self.series {
+            series.baseline = series.values.first().map(|e| e.duration);
+        }
+        self.y_axis = YAxis::Percent;
+    }
+
+    /// Rescales each series as a percent of its value at `commit`. Series
+    /// without a timing for `commit` are dropped.
+    pub fn rebase_to(&mut self, commit: &CommitId) {
+        for series in &mut self.series {
+            series.baseline = series.values.iter().find(|e| e.commit.id == *commit).map(|e| e.duration);
+            if series.baseline.is_none() {
+                eprintln!("warning: no timings for baseline {} in {}. not plotting it", commit.as_str(), series.caption());
+            }
+        }
+        self.series.retain(|s| s.baseline.is_some());
+        self.y_axis = YAxis::Percent;
+    }
+
+    /// Marks plotted commits with a tag matching `pattern`
+    pub fn mark_tags(&mut self, pattern: &str) {
+        let mut markers = vec![];
+        for entry in self.commits() {
+            for tag in entry.commit.tags.iter().filter(|t| glob_match(pattern, t)) {
+                markers.push(Marker {
+                    x: self.x_axis.x(entry),
+                    kind: MarkerKind::Tag,
+                    label: tag.clone(),
+                });
+            }
+        }
+        self.markers.extend(markers);
+    }
+
+    /// Marks each plotted commit whose toolchain differs from the
+    /// previous one's. Commits without a recorded toolchain are skipped.
+    pub fn mark_toolchains(&mut self, data: &Data) {
+        let mut markers = vec![];
+        let mut last = None;
+        for entry in self.commits() {
+            if let Some(toolchain) = data.toolchain(&entry.commit.id) {
+                if last.is_some() && last != Some(toolchain) {
+                    markers.push(Marker {
+                        x: self.x_axis.x(entry),
+                        kind: MarkerKind::Toolchain,
+                        label: toolchain.to_string(),
+                    });
+                }
+                last = Some(toolchain);
+            }
+        }
+        self.markers.extend(markers);
+    }
+
+    /// Marks plotted commits with events from `maptime annotate`
+    pub fn mark_events(&mut self, data: &Data) {
+        let mut markers = vec![];
+        for entry in self.commits() {
+            for event in data.events.get(&entry.commit.id).into_iter().flatten() {
+                markers.push(Marker {
+                    x: self.x_axis.x(entry),
+                    kind: MarkerKind::Event,
+                    label: event.clone(),
+                });
+            }
+        }
+        self.markers.extend(markers);
+    }
+
+    /// Whether the slowest series is ten times slower than the fastest
+    pub fn spans_magnitudes(&self) -> bool {
+        let means: Vec<f64> = self.series.iter()
+            .filter(|s| !s.values.is_empty())
+            .map(|s| s.values.iter().map(|e| s.y(e)).sum::<f64>() / s.values.len() as f64)
+            .collect();
+        let min = means.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let max = means.iter().cloned().fold(0.0, f64::max);
+        min > 0.0 && max / min >= 10.0
+    }
+
+    /// The y-axis bounds, padded around the values. A log scale starts at
+    /// the smallest positive value, since it can't show zero.
+    pub fn y_range(&self, log_scale: bool) -> (f64, f64) {
+        let ys = || self.series.iter().flat_map(|s| s.values.iter().map(move |e| s.y(e)));
+        let max = ys().fold(0.0, f64::max);
+        if !log_scale {
+            return (0.0, max * 1.1);
+        }
+        let min = ys().filter(|y| *y > 0.0).fold(f64::INFINITY, f64::min);
+        if !min.is_finite() {
+            // Nothing positive to plot
+            return (1.0, 10.0);
+        }
+        (min * 0.9, max * 1.1)
+    }
+}
+
+/// Matches `*` and `?` wildcards, like git's tag patterns
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    // Positions to backtrack to after the last `*`
+    let (mut p, mut i) = (0, 0);
+    let mut star = None;
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((sp, si)) = star {
+            p = sp + 1;
+            i = si + 1;
+            star = Some((sp, si + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Entry {
+    pub fn stddev(&self) -> Duration {
+        stats::stddev(&self.samples)
+    }
+
+    /// The date, commit, author and description shown when hovering over
+    /// the point, one per line
+    pub fn hover_text(&self, x_axis: XAxis) -> String {
+        format!("{}\n{}\n{}\n{}",
+                x_axis.date(&self.commit).format("%Y-%m-%d"),
+                self.commit.id.as_str(),
+                self.commit.author.as_ref().map(String::as_str).unwrap_or("<unknown author>"),
+                self.commit.description().unwrap_or("<no description>"))
+    }
+}
+
+impl Series {
+    /// Like `dev+full`, or `dev+full@builder1` when split by machine
+    pub fn caption(&self) -> String {
+        match self.machine {
+            Some(ref machine) => format!("{}@{}", self.key.name(), machine),
+            None => self.key.name(),
+        }
+    }
+
+    /// The plotted value, in seconds or percent of the baseline
+    pub fn y(&self, entry: &Entry) -> f64 {
+        match self.baseline {
+            Some(baseline) => entry.duration.as_secs_f64() / baseline.as_secs_f64() * 100.0,
+            None => entry.duration.as_secs_f64(),
+        }
+    }
+}
+
+impl SeriesKey {
+    /// Every profile and rebuild type maptime builds with the default
+    /// configuration and the project's toolchain, in plotting order
+    pub fn all() -> Vec<SeriesKey> {
+        let rebuild_types = [
+            RebuildType::Full,
+            RebuildType::Partial,
+            RebuildType::Workspace,
+            RebuildType::NoOp,
+            RebuildType::Deps,
+        ];
+        [Profile::Dev, Profile::Release].iter().flat_map(|&profile| {
+            rebuild_types.iter().map(move |&rebuild_type| SeriesKey {
+                profile,
+                rebuild_type,
+                config: None,
+                toolchain_name: None,
+            })
+        }).collect()
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = format!("{}+{}", self.profile.as_ref(), self.rebuild_type.as_ref());
+        if let Some(ref config) = self.config {
+            name.push('+');
+            name.push_str(config);
+        }
+        if let Some(ref toolchain_name) = self.toolchain_name {
+            name.push(':');
+            name.push_str(toolchain_name);
+        }
+        name
+    }
+}
+
+impl XAxis {
+    /// Whether the axis is a time axis, or categorical by commit
+    pub fn is_time(&self) -> bool {
+        match self {
+            XAxis::CommitterDate | XAxis::AuthorDate => true,
+            XAxis::Index | XAxis::Topological => false,
+        }
+    }
+
+    pub fn date(&self, commit: &Commit) -> DateTime<Utc> {
+        match self {
+            XAxis::AuthorDate => commit.author_date.unwrap_or(commit.date),
+            _ => commit.date,
+        }
+    }
+
+    pub fn x(&self, entry: &Entry) -> i64 {
+        if self.is_time() {
+            self.date(&entry.commit).timestamp()
+        } else {
+            entry.index as i64
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            XAxis::CommitterDate => "committer date",
+            XAxis::AuthorDate => "author date",
+            XAxis::Index | XAxis::Topological => "commit",
+        }
+    }
+}
+
+impl YAxis {
+    pub fn label(&self) -> &str {
+        match self {
+            YAxis::Seconds => "compile-time",
+            YAxis::Percent => "compile-time (% of baseline)",
+        }
+    }
+
+    pub fn unit(&self) -> &str {
+        match self {
+            YAxis::Seconds => "s",
+            YAxis::Percent => "%",
+        }
+    }
+}
+
+impl MarkerKind {
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self {
+            MarkerKind::Tag => (40, 80, 180),
+            MarkerKind::Toolchain => (190, 40, 40),
+            MarkerKind::Event => (40, 140, 60),
+        }
+    }
+}
+
+impl AsRef<str> for MarkerKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            MarkerKind::Tag => "tag",
+            MarkerKind::Toolchain => "toolchain",
+            MarkerKind::Event => "event",
+        }
+    }
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Format, Error> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        Format::from_str(&ext.to_lowercase()).map_err(|_| Error::UnknownFormat(path.to_owned()))
+    }
+}
+
+impl AsRef<str> for Backend {
+    fn as_ref(&self) -> &str {
+        match self {
+            Backend::Native => "native",
+            Backend::Gnuplot => "gnuplot",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Backend, ParseError> {
+        match s {
+            "native" => Ok(Backend::Native),
+            "gnuplot" => Ok(Backend::Gnuplot),
+            _ => Err(ParseError::Backend(s.to_string())),
+        }
+    }
+}
+
+impl AsRef<str> for Format {
+    fn as_ref(&self) -> &str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Pdf => "pdf",
+            Format::Html => "html",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Format, ParseError> {
+        match s {
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            "pdf" => Ok(Format::Pdf),
+            "html" => Ok(Format::Html),
+            _ => Err(ParseError::Format(s.to_string())),
+        }
+    }
+}
+
+impl AsRef<str> for XAxis {
+    fn as_ref(&self) -> &str {
+        match self {
+            XAxis::CommitterDate => "committer-date",
+            XAxis::AuthorDate => "author-date",
+            XAxis::Index => "index",
+            XAxis::Topological => "topo",
+        }
+    }
+}
+
+impl FromStr for XAxis {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<XAxis, ParseError> {
+        match s {
+            "committer-date" => Ok(XAxis::CommitterDate),
+            "author-date" => Ok(XAxis::AuthorDate),
+            "index" => Ok(XAxis::Index),
+            "topo" => Ok(XAxis::Topological),
+            _ => Err(ParseError::XAxis(s.to_string())),
+        }
+    }
+}
+
+impl AsRef<str> for YAxis {
+    fn as_ref(&self) -> &str {
+        match self {
+            YAxis::Seconds => "seconds",
+            YAxis::Percent => "percent",
+        }
+    }
+}
+
+impl FromStr for YAxis {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<YAxis, ParseError> {
+        match s {
+            "seconds" => Ok(YAxis::Seconds),
+            "percent" => Ok(YAxis::Percent),
+            _ => Err(ParseError::YAxis(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for SeriesKey {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<SeriesKey, ParseError> {
+        let (series, toolchain_name) = match s.find(':') {
+            Some(i) => (&s[..i], Some(s[i + 1..].to_string())),
+            None => (s, None),
+        };
+        let mut parts = series.splitn(3, '+');
+        let profile = parts.next().and_then(|p| Profile::from_str(p).ok());
+        let rebuild_type = parts.next().and_then(|r| RebuildType::from_str(r).ok());
+        let config = parts.next().map(str::to_string);
+        match (profile, rebuild_type) {
+            (Some(profile), Some(rebuild_type)) => Ok(SeriesKey { profile, rebuild_type, config, toolchain_name }),
+            _ => Err(ParseError::Series(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum ParseError {
+    #[display(fmt = "unknown plot backend '{}'. expected native or gnuplot", _0)]
+    Backend(String),
+    #[display(fmt = "unknown plot format '{}'. expected svg, png, pdf or html", _0)]
+    Format(String),
+    #[display(fmt = "unknown x-axis '{}'. expected committer-date, author-date, index or topo", _0)]
+    XAxis(String),
+    #[display(fmt = "unknown y-axis '{}'. expected seconds or percent", _0)]
+    YAxis(String),
+    #[display(fmt = "unknown series '{}'. expected profile+rebuild-type, optionally +config and :toolchain, like dev+full, dev+full+lld or dev+full:nightly", _0)]
+    Series(String),
+}
+
+impl StdError for ParseError { }
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "can't determine plot format of {}. use --image-format or a .svg, .png, .pdf or .html extension", "_0.display()")]
+    UnknownFormat(PathBuf),
+    #[display(fmt = "the {} backend can't produce {} plots", "_0.as_ref()", "_1.as_ref()")]
+    UnsupportedFormat(Backend, Format),
+    #[display(fmt = "maptime was built without the gnuplot feature")]
+    GnuplotDisabled,
+    #[display(fmt = "drawing native plot")]
+    Native(crate::native::Error),
+    #[cfg(feature = "gnuplot")]
+    #[display(fmt = "running gnuplot")]
+    Gnuplot(crate::gnuplot::Error),
+    #[display(fmt = "writing html plot")]
+    Report(crate::report::Error),
+    #[display(fmt = "drawing terminal plot")]
+    Terminal(crate::terminal::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::UnknownFormat(_) => None,
+            Error::UnsupportedFormat(..) => None,
+            Error::GnuplotDisabled => None,
+            Error::Native(ref e) => Some(e),
+            #[cfg(feature = "gnuplot")]
+            Error::Gnuplot(ref e) => Some(e),
+            Error::Report(ref e) => Some(e),
+            Error::Terminal(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<crate::native::Error> for Error {
+    fn from(e: crate::native::Error) -> Error {
+        Error::Native(e)
+    }
+}
+
+#[cfg(feature = "gnuplot")]
+impl From<crate::gnuplot::Error> for Error {
+    fn from(e: crate::gnuplot::Error) -> Error {
+        Error::Gnuplot(e)
+    }
+}
+
+impl From<crate::report::Error> for Error {
+    fn from(e: crate::report::Error) -> Error {
+        Error::Report(e)
+    }
+}
+
+impl From<crate::terminal::Error> for Error {
+    fn from(e: crate::terminal::Error) -> Error {
+        Error::Terminal(e)
+    }
+}
@@ -0,0 +1,181 @@
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+use crate::analysis::Regression;
+use crate::data::Profile;
+use crate::plot::PlotData;
+
+const TEMPLATE: &str = include_str!("report.html");
+
+pub struct ReportOptions<'a> {
+    pub title: &'a str,
+    /// A URL with `{sha}` in place of the commit ID
+    pub commit_url: Option<&'a str>,
+    /// The regression threshold, as a fraction
+    pub threshold: f64,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    x_time: bool,
+    x_label: &'a str,
+    y_unit: &'a str,
+    commit_url: Option<&'a str>,
+    /// Short commit IDs by x position, for categorical axes
+    commits: Vec<&'a str>,
+    charts: Vec<Chart<'a>>,
+    markers: Vec<MarkerPoint<'a>>,
+    threshold: f64,
+    regressions: Vec<RegressionRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct Chart<'a> {
+    id: usize,
+    name: &'a str,
+    series: Vec<ChartSeries<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChartSeries<'a> {
+    name: String,
+    points: Vec<Point<'a>>,
+}
+
+#[derive(Serialize)]
+struct Point<'a> {
+    x: i64,
+    y: f64,
+    sha: &'a str,
+    date: String,
+    author: Option<&'a str>,
+    subject: Option<&'a str>,
+    note: Option<&'a str>,
+    mean: f64,
+    stddev: f64,
+    samples: usize,
+}
+
+#[derive(Serialize)]
+struct MarkerPoint<'a> {
+    x: i64,
+    kind: &'a str,
+    label: &'a str,
+}
+
+#[derive(Serialize)]
+struct RegressionRow<'a> {
+    series: String,
+    before: &'a str,
+    after: &'a str,
+    subject: Option<&'a str>,
+    before_secs: f64,
+    after_secs: f64,
+    change_pct: f64,
+}
+
+/// Writes a self-contained interactive HTML report with one chart per profile
+pub fn write_html(data: &PlotData, regressions: &[Regression], opts: &ReportOptions, file: &Path) -> Result<(), Error> {
+    let x_axis = data.x_axis;
+
+    let mut commits = vec![];
+    if !x_axis.is_time() {
+        for entry in data.commits() {
+            commits.resize(entry.index + 1, "");
+            commits[entry.index] = entry.commit.short_id();
+        }
+    }
+
+    let mut charts = vec![];
+    for (id, profile) in [Profile::Dev, Profile::Release].iter().enumerate() {
+        let series = data.series.iter().filter(|s| s.key.profile == *profile).map(|s| {
+            ChartSeries {
+                name: s.caption(),
+                points: s.values.iter().map(|e| Point {
+                    // JavaScript dates are in milliseconds
+                    x: if x_axis.is_time() { x_axis.x(e) * 1000 } else { x_axis.x(e) },
+                    y: s.y(e),
+                    sha: e.commit.id.as_str(),
+                    date: x_axis.date(&e.commit).format("%Y-%m-%d %H:%M").to_string(),
+                    author: e.commit.author.as_ref().map(String::as_str),
+                    subject: e.commit.subject.as_ref().map(String::as_str),
+                    note: e.commit.note.as_ref().map(String::as_str),
+                    mean: e.duration.as_secs_f64(),
+                    stddev: e.stddev().as_secs_f64(),
+                    samples: e.samples.len(),
+                }).collect(),
+            }
+        }).collect();
+        charts.push(Chart {
+            id,
+            name: profile.as_ref(),
+            series,
+        });
+    }
+
+    let markers = data.markers.iter().map(|m| MarkerPoint {
+        x: if x_axis.is_time() { m.x * 1000 } else { m.x },
+        kind: m.kind.as_ref(),
+        label: &m.label,
+    }).collect();
+
+    let regressions = regressions.iter().map(|r| RegressionRow {
+        series: r.key.name(),
+        before: r.before.commit.id.as_str(),
+        after: r.after.commit.id.as_str(),
+        subject: r.after.commit.subject.as_ref().map(String::as_str),
+        before_secs: r.before.duration.as_secs_f64(),
+        after_secs: r.after.duration.as_secs_f64(),
+        change_pct: r.change() * 100.0,
+    }).collect();
+
+    let report = Report {
+        x_time: x_axis.is_time(),
+        x_label: x_axis.label(),
+        y_unit: data.y_axis.unit(),
+        commit_url: opts.commit_url,
+        commits,
+        charts,
+        markers,
+        threshold: opts.threshold * 100.0,
+        regressions,
+    };
+
+    let json = serde_json::to_string(&report).map_err(Error::Json)?;
+    // Keep the data from closing its script element
+    let json = json.replace("</", "<\\/");
+
+    let html = TEMPLATE
+        .replace("{{title}}", &escape_html(opts.title))
+        .replace("{{data}}", &json);
+
+    fs::write(file, html).map_err(Error::Io)?;
+
+    eprintln!("report in {}", file.display());
+
+    Ok(())
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "serializing report data")]
+    Json(serde_json::Error),
+    #[display(fmt = "writing report")]
+    Io(std::io::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Json(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+        }
+    }
+}
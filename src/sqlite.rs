@@ -0,0 +1,657 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Row, NO_PARAMS};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::commit_id::CommitId;
+use crate::commit_list::CommitInput;
+use crate::data::{Commit, Data, Timing, VERSION};
+use crate::lock::LockMode;
+use crate::migrate::{self, Report};
+use crate::storage::{Error, Storage, TimingQuery};
+
+/// Dates are stored as fixed-width RFC 3339 text so they sort by time, and
+/// lists as space-separated text.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS unresolved_commits (
+        position INTEGER PRIMARY KEY,
+        id TEXT NOT NULL,
+        note TEXT
+    );
+    CREATE TABLE IF NOT EXISTS commits (
+        id TEXT PRIMARY KEY,
+        date TEXT NOT NULL,
+        note TEXT,
+        author TEXT,
+        author_date TEXT,
+        subject TEXT,
+        parents TEXT NOT NULL,
+        tags TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS timings (
+        id INTEGER PRIMARY KEY,
+        commit_id TEXT NOT NULL,
+        profile TEXT NOT NULL,
+        rebuild_type TEXT NOT NULL,
+        start TEXT NOT NULL,
+        duration_ns INTEGER NOT NULL,
+        result TEXT NOT NULL,
+        toolchain TEXT,
+        machine TEXT,
+        exit_code INTEGER,
+        error TEXT,
+        log TEXT,
+        config TEXT,
+        toolchain_name TEXT
+    );
+    CREATE INDEX IF NOT EXISTS timings_by_commit ON timings (commit_id);
+    CREATE INDEX IF NOT EXISTS timings_by_config ON timings (profile, rebuild_type);
+    CREATE INDEX IF NOT EXISTS timings_by_start ON timings (start);
+    CREATE TABLE IF NOT EXISTS configs (
+        name TEXT PRIMARY KEY,
+        definition TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        commit_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+";
+
+const TIMING_COLUMNS: &str = "commit_id, profile, rebuild_type, start, duration_ns, result, toolchain, machine, \
+                              exit_code, error, log, config, toolchain_name";
+
+/// The version each upgrade moves a database up from, what it changes,
+/// and its statements. Tables created by `SCHEMA` are already current.
+const UPGRADES: &[(u32, &str, &str)] = &[
+    (1, "add exit code, error and log columns to timings",
+     "ALTER TABLE timings ADD COLUMN exit_code INTEGER;
+      ALTER TABLE timings ADD COLUMN error TEXT;
+      ALTER TABLE timings ADD COLUMN log TEXT;"),
+    (2, "add a build configuration column to timings and a configuration table",
+     "ALTER TABLE timings ADD COLUMN config TEXT;"),
+    (3, "add a toolchain name column to timings",
+     "ALTER TABLE timings ADD COLUMN toolchain_name TEXT;"),
+];
+
+/// The database as tables in an SQLite file
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens a current database. `LockMode::Shared` opens it read-only,
+    /// and `LockMode::Exclusive` creates it if missing.
+    pub fn open(path: &Path, mode: LockMode) -> Result<SqliteStorage, Error> {
+        let conn = match mode {
+            LockMode::Shared if version(path)?.is_some() => {
+                Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+            }
+            LockMode::Shared => {
+                // Reads as empty without creating the file
+                let conn = Connection::open_in_memory()?;
+                conn.execute_batch(SCHEMA)?;
+                conn
+            }
+            LockMode::Exclusive => {
+                let conn = Connection::open(path)?;
+                if read_version(&conn)?.is_none() {
+                    conn.execute_batch("BEGIN IMMEDIATE")?;
+                    conn.execute_batch(SCHEMA)?;
+                    conn.execute("INSERT INTO meta (key, value) VALUES ('version', ?)", &[VERSION.to_string()])?;
+                    conn.execute_batch("COMMIT")?;
+                }
+                conn
+            }
+        };
+
+        Ok(SqliteStorage { conn })
+    }
+}
+
+/// The version of the database at `path`, read without changing it.
+/// `None` if there is no database yet.
+pub fn version(path: &Path) -> Result<Option<u32>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    read_version(&conn)
+}
+
+/// Reports what `upgrade` would change, without changing anything. `None`
+/// if the database is missing or already current.
+pub fn check(path: &Path) -> Result<Option<Report>, Error> {
+    let from = match version(path)? {
+        Some(version) if version < VERSION => version,
+        _ => return Ok(None),
+    };
+    let changes = UPGRADES.iter()
+        .filter(|&&(version, _, _)| version >= from)
+        .map(|&(_, change, _)| change.to_string())
+        .collect();
+    Ok(Some(Report { from, to: VERSION, changes }))
+}
+
+/// Upgrades the database at `path` in place, first copying it to
+/// `migrate::backup_path`. Take an exclusive lock first. `None` if it is
+/// missing or already current.
+pub fn upgrade(path: &Path) -> Result<Option<Report>, Error> {
+    let report = match check(path)? {
+        Some(report) => report,
+        None => return Ok(None),
+    };
+
+    let backup = migrate::backup_path(path, report.from);
+    fs::copy(path, &backup).map_err(|e| Error::Migrate(migrate::Error::Io(e)))?;
+
+    let conn = Connection::open(path)?;
+    conn.execute_batch("BEGIN IMMEDIATE")?;
+    // Creates missing tables. Existing ones keep their old columns
+    conn.execute_batch(SCHEMA)?;
+    for &(_, _, upgrade) in UPGRADES.iter().filter(|&&(from, _, _)| from >= report.from) {
+        conn.execute_batch(upgrade)?;
+    }
+    conn.execute("UPDATE meta SET value = ? WHERE key = 'version'", &[VERSION.to_string()])?;
+    conn.execute_batch("COMMIT")?;
+
+    Ok(Some(report))
+}
+
+/// `None` for a new, empty database
+fn read_version(conn: &Connection) -> Result<Option<u32>, Error> {
+    let tables: i64 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'meta'", NO_PARAMS, |row| row.get(0))?;
+    if tables == 0 {
+        return Ok(None);
+    }
+    let version: Option<String> = conn.query_row(
+        "SELECT value FROM meta WHERE key = 'version'", NO_PARAMS, |row| row.get(0)).optional()?;
+    let version = match version {
+        Some(version) => version.parse().map_err(|_| Error::Corrupt("version"))?,
+        None => return Ok(None),
+    };
+    if version > VERSION {
+        return Err(Error::Migrate(migrate::Error::TooNew(u64::from(version))));
+    }
+    Ok(Some(version))
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<Data, Error> {
+        let mut data = Data::default();
+
+        let mut stmt = self.conn.prepare("SELECT id, note FROM unresolved_commits ORDER BY position")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (id, note) = row?;
+            data.unresolved_commits.push(CommitInput { id: commit_id(&id)?, note });
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, date, note, author, author_date, subject, parents, tags FROM commits")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok(CommitRow {
+            id: row.get(0)?,
+            date: row.get(1)?,
+            note: row.get(2)?,
+            author: row.get(3)?,
+            author_date: row.get(4)?,
+            subject: row.get(5)?,
+            parents: row.get(6)?,
+            tags: row.get(7)?,
+        }))?;
+        for row in rows {
+            let commit = row?.parse()?;
+            data.commits.insert(commit.id.clone(), commit);
+        }
+
+        let sql = format!("SELECT {} FROM timings ORDER BY id", TIMING_COLUMNS);
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(NO_PARAMS, TimingRow::read)?;
+        for row in rows {
+            let (commit, timing) = row?.parse()?;
+            data.timings.entry(commit).or_insert(vec![]).push(timing);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT name, definition FROM configs")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (name, definition) = row?;
+            let config = serde_json::from_str(&definition).map_err(|_| Error::Corrupt("build config"))?;
+            data.configs.insert(name, config);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT commit_id, event FROM events ORDER BY id")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (commit, event) = row?;
+            data.events.entry(commit_id(&commit)?).or_insert(vec![]).push(event);
+        }
+
+        Ok(data)
+    }
+
+    fn save(&mut self, data: &Data) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute_batch("
+            DELETE FROM unresolved_commits;
+            DELETE FROM commits;
+            DELETE FROM timings;
+            DELETE FROM configs;
+            DELETE FROM events;
+        ")?;
+
+        {
+            let mut stmt = tx.prepare("INSERT INTO unresolved_commits (id, note) VALUES (?, ?)")?;
+            for commit in &data.unresolved_commits {
+                stmt.execute(&[Some(commit.id.as_str()), commit.note.as_ref().map(String::as_str)])?;
+            }
+
+            let mut stmt = tx.prepare(
+                "INSERT INTO commits (id, date, note, author, author_date, subject, parents, tags)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)")?;
+            for commit in data.commits.values() {
+                let parents: Vec<&str> = commit.parents.iter().map(CommitId::as_str).collect();
+                stmt.execute(&[
+                    Some(commit.id.as_str().to_string()),
+                    Some(format_date(&commit.date)),
+                    commit.note.clone(),
+                    commit.author.clone(),
+                    commit.author_date.as_ref().map(format_date),
+                    commit.subject.clone(),
+                    Some(parents.join(" ")),
+                    Some(commit.tags.join(" ")),
+                ])?;
+            }
+
+            for (commit, timings) in &data.timings {
+                insert_timings(&tx, commit, timings)?;
+            }
+
+            let mut stmt = tx.prepare("INSERT INTO configs (name, definition) VALUES (?, ?)")?;
+            for (name, config) in &data.configs {
+                let definition = serde_json::to_string(config).expect("build config serializes");
+                stmt.execute(&[name, &definition])?;
+            }
+
+            let mut stmt = tx.prepare("INSERT INTO events (commit_id, event) VALUES (?, ?)")?;
+            for (commit, events) in &data.events {
+                for event in events {
+                    stmt.execute(&[commit.as_str(), event])?;
+                }
+            }
+        }
+
+        Ok(tx.commit()?)
+    }
+
+    fn add_timings(&mut self, commit: &CommitId, timings: &[Timing]) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        insert_timings(&tx, commit, timings)?;
+        Ok(tx.commit()?)
+    }
+
+    fn query_timings(&mut self, query: &TimingQuery) -> Result<Vec<(CommitId, Timing)>, Error> {
+        let mut conditions = vec!["1".to_string()];
+        let mut params = vec![];
+        if let Some(ref commit) = query.commit {
+            conditions.push("commit_id = ?".to_string());
+            params.push(commit.as_str().to_string());
+        }
+        if let Some(ref series) = query.series {
+            conditions.push("profile = ?".to_string());
+            params.push(series.profile.as_ref().to_string());
+            conditions.push("rebuild_type = ?".to_string());
+            params.push(series.rebuild_type.as_ref().to_string());
+            match series.config {
+                Some(ref config) => {
+                    conditions.push("config = ?".to_string());
+                    params.push(config.clone());
+                }
+                None => conditions.push("config IS NULL".to_string()),
+            }
+            match series.toolchain_name {
+                Some(ref toolchain_name) => {
+                    conditions.push("toolchain_name = ?".to_string());
+                    params.push(toolchain_name.clone());
+                }
+                None => conditions.push("toolchain_name IS NULL".to_string()),
+            }
+        }
+        if let Some(ref since) = query.since {
+            conditions.push("start >= ?".to_string());
+            params.push(format_date(since));
+        }
+        if let Some(ref until) = query.until {
+            conditions.push("start < ?".to_string());
+            params.push(format_date(until));
+        }
+        if let Some(ref machine) = query.machine {
+            conditions.push("machine = ?".to_string());
+            params.push(machine.clone());
+        }
+
+        // Timings without a commit don't occur, but sort them last
+        let sql = format!("SELECT {} FROM timings LEFT JOIN commits ON commits.id = commit_id
+                           WHERE {} ORDER BY commits.date IS NULL, commits.date, commit_id, timings.id",
+                          TIMING_COLUMNS, conditions.join(" AND "));
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(&params, TimingRow::read)?;
+        let mut results = vec![];
+        for row in rows {
+            results.push(row?.parse()?);
+        }
+        Ok(results)
+    }
+}
+
+fn insert_timings(conn: &Connection, commit: &CommitId, timings: &[Timing]) -> Result<(), Error> {
+    let sql = format!("INSERT INTO timings ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", TIMING_COLUMNS);
+    let mut stmt = conn.prepare(&sql)?;
+    for timing in timings {
+        stmt.execute(rusqlite::params![
+            commit.as_str(),
+            timing.profile.as_ref(),
+            timing.rebuild_type.as_ref(),
+            format_date(&timing.start),
+            timing.duration.as_nanos() as i64,
+            timing.result.as_ref(),
+            timing.toolchain,
+            timing.machine,
+            timing.exit_code,
+            timing.error,
+            timing.log.as_ref().map(|l| l.to_string_lossy()),
+            timing.config,
+            timing.toolchain_name,
+        ])?;
+    }
+    Ok(())
+}
+
+struct CommitRow {
+    id: String,
+    date: String,
+    note: Option<String>,
+    author: Option<String>,
+    author_date: Option<String>,
+    subject: Option<String>,
+    parents: String,
+    tags: String,
+}
+
+impl CommitRow {
+    fn parse(self) -> Result<Commit, Error> {
+        Ok(Commit {
+            id: commit_id(&self.id)?,
+            date: parse_date(&self.date)?,
+            note: self.note,
+            author: self.author,
+            author_date: self.author_date.as_ref().map(|d| parse_date(d)).transpose()?,
+            subject: self.subject,
+            parents: self.parents.split_whitespace().map(commit_id).collect::<Result<_, _>>()?,
+            tags: self.tags.split_whitespace().map(str::to_string).collect(),
+        })
+    }
+}
+
+struct TimingRow {
+    commit: String,
+    profile: String,
+    rebuild_type: String,
+    start: String,
+    duration_ns: i64,
+    result: String,
+    toolchain: Option<String>,
+    machine: Option<String>,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    log: Option<String>,
+    config: Option<String>,
+    toolchain_name: Option<String>,
+}
+
+impl TimingRow {
+    /// Reads the columns in `TIMING_COLUMNS`
+    fn read(row: &Row) -> rusqlite::Result<TimingRow> {
+        Ok(TimingRow {
+            commit: row.get(0)?,
+            profile: row.get(1)?,
+            rebuild_type: row.get(2)?,
+            start: row.get(3)?,
+            duration_ns: row.get(4)?,
+            result: row.get(5)?,
+            toolchain: row.get(6)?,
+            machine: row.get(7)?,
+            exit_code: row.get(8)?,
+            error: row.get(9)?,
+            log: row.get(10)?,
+            config: row.get(11)?,
+            toolchain_name: row.get(12)?,
+        })
+    }
+
+    fn parse(self) -> Result<(CommitId, Timing), Error> {
+        let timing = Timing {
+            profile: self.profile.parse().map_err(|_| Error::Corrupt("profile"))?,
+            rebuild_type: self.rebuild_type.parse().map_err(|_| Error::Corrupt("rebuild type"))?,
+            start: parse_date(&self.start)?,
+            duration: Duration::from_nanos(self.duration_ns as u64),
+            result: self.result.parse().map_err(|_| Error::Corrupt("build result"))?,
+            toolchain: self.toolchain,
+            machine: self.machine,
+            exit_code: self.exit_code,
+            error: self.error,
+            log: self.log.map(PathBuf::from),
+            config: self.config,
+            toolchain_name: self.toolchain_name,
+        };
+        Ok((commit_id(&self.commit)?, timing))
+    }
+}
+
+fn commit_id(s: &str) -> Result<CommitId, Error> {
+    CommitId::from_str(s).map_err(|_| Error::Corrupt("commit id"))
+}
+
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn parse_date(s: &str) -> Result<DateTime<Utc>, Error> {
+    let date = DateTime::parse_from_rfc3339(s).map_err(|_| Error::Corrupt("date"))?;
+    Ok(date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::data::{BuildConfig, BuildResult, Profile, RebuildType};
+    use crate::plot::SeriesKey;
+    use crate::testing::TempDir;
+
+    const A: &str = "67a32593bf76464ff650f7b116af403d7f4613c9";
+    const B: &str = "b7a23425bfe5472710a21c7c9aeb2a5266802189";
+
+    fn id(s: &str) -> CommitId {
+        CommitId::from_str(s).unwrap()
+    }
+
+    fn date(s: &str) -> DateTime<Utc> {
+        parse_date(s).unwrap()
+    }
+
+    fn timing(profile: Profile, rebuild_type: RebuildType, start: &str) -> Timing {
+        Timing {
+            profile,
+            rebuild_type,
+            start: date(start),
+            duration: Duration::from_nanos(1_234_567_891),
+            result: BuildResult::Success,
+            toolchain: None,
+            machine: None,
+            exit_code: None,
+            error: None,
+            log: None,
+            config: None,
+            toolchain_name: None,
+        }
+    }
+
+    /// A with a timing setting every field and one with the defaults, and B
+    /// with a failed release build
+    fn data() -> Data {
+        let mut data = Data::default();
+        data.unresolved_commits.push(CommitInput { id: id("c9f1ab2a6f0b22a7bd1e3a1b3b8c1c2e4d5f6a7b"), note: Some("later".to_string()) });
+        data.commits.insert(id(A), Commit {
+            id: id(A),
+            date: date("2019-10-01T00:00:00Z"),
+            note: Some("switched to lld".to_string()),
+            author: Some("a <a@b>".to_string()),
+            author_date: Some(date("2019-09-30T23:00:00.5Z")),
+            subject: Some("subject".to_string()),
+            parents: vec![id(B)],
+            tags: vec!["v1.0".to_string(), "release".to_string()],
+        });
+        data.commits.insert(id(B), Commit {
+            id: id(B),
+            date: date("2019-10-02T00:00:00Z"),
+            note: None,
+            author: None,
+            author_date: None,
+            subject: None,
+            parents: vec![],
+            tags: vec![],
+        });
+
+        let mut every_field = timing(Profile::Dev, RebuildType::Full, "2019-10-03T01:00:00.123456789Z");
+        every_field.result = BuildResult::Timeout;
+        every_field.toolchain = Some("rustc 1.38.0".to_string());
+        every_field.machine = Some("ci".to_string());
+        every_field.exit_code = Some(101);
+        every_field.error = Some("error[E0425]: cannot find value".to_string());
+        every_field.log = Some(PathBuf::from("maptime-logs/dev-full.log"));
+        every_field.config = Some("lld".to_string());
+        every_field.toolchain_name = Some("nightly".to_string());
+        let default = timing(Profile::Dev, RebuildType::Full, "2019-10-03T02:00:00Z");
+        data.timings.insert(id(A), vec![every_field, default]);
+
+        let mut failed = timing(Profile::Release, RebuildType::Partial, "2019-10-03T03:00:00Z");
+        failed.result = BuildResult::Failure;
+        data.timings.insert(id(B), vec![failed]);
+
+        let mut env = BTreeMap::new();
+        env.insert("CARGO_INCREMENTAL".to_string(), "0".to_string());
+        data.configs.insert("lld".to_string(), BuildConfig {
+            env,
+            rustflags: Some("-C link-arg=-fuse-ld=lld".to_string()),
+            config: vec!["profile.dev.codegen-units=1".to_string()],
+        });
+        data.events.insert(id(A), vec!["switched to lld".to_string(), "upgraded ci".to_string()]);
+        data
+    }
+
+    fn json(data: &Data) -> serde_json::Value {
+        serde_json::to_value(data).unwrap()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("sqlite-save");
+        let path = dir.join("maptime.sqlite");
+
+        let mut storage = SqliteStorage::open(&path, LockMode::Exclusive).unwrap();
+        storage.save(&data()).unwrap();
+        assert_eq!(json(&storage.load().unwrap()), json(&data()));
+
+        // Saving again replaces rather than adds
+        storage.save(&data()).unwrap();
+        drop(storage);
+        let mut storage = SqliteStorage::open(&path, LockMode::Shared).unwrap();
+        assert_eq!(json(&storage.load().unwrap()), json(&data()));
+    }
+
+    #[test]
+    fn query_timings_filters() {
+        let dir = TempDir::new("sqlite-query");
+        let mut storage = SqliteStorage::open(&dir.join("maptime.sqlite"), LockMode::Exclusive).unwrap();
+        storage.save(&data()).unwrap();
+
+        let mut starts = |query: TimingQuery| -> Vec<String> {
+            storage.query_timings(&query).unwrap().iter().map(|(_, t)| format_date(&t.start)).collect()
+        };
+        let every_field = "2019-10-03T01:00:00.123456789Z";
+        let default = "2019-10-03T02:00:00.000000000Z";
+        let failed = "2019-10-03T03:00:00.000000000Z";
+        let dev_full = SeriesKey {
+            profile: Profile::Dev,
+            rebuild_type: RebuildType::Full,
+            config: None,
+            toolchain_name: None,
+        };
+
+        assert_eq!(starts(TimingQuery::default()), vec![every_field, default, failed]);
+        assert_eq!(starts(TimingQuery { commit: Some(id(B)), ..TimingQuery::default() }), vec![failed]);
+        assert_eq!(starts(TimingQuery { series: Some(dev_full.clone()), ..TimingQuery::default() }), vec![default]);
+        let lld_nightly = SeriesKey {
+            config: Some("lld".to_string()),
+            toolchain_name: Some("nightly".to_string()),
+            ..dev_full.clone()
+        };
+        assert_eq!(starts(TimingQuery { series: Some(lld_nightly), ..TimingQuery::default() }), vec![every_field]);
+        let lld_only = SeriesKey { config: Some("lld".to_string()), ..dev_full };
+        assert!(starts(TimingQuery { series: Some(lld_only), ..TimingQuery::default() }).is_empty());
+        assert_eq!(starts(TimingQuery { since: Some(date(default)), ..TimingQuery::default() }), vec![default, failed]);
+        assert_eq!(starts(TimingQuery { until: Some(date(default)), ..TimingQuery::default() }), vec![every_field]);
+        assert_eq!(starts(TimingQuery { machine: Some("ci".to_string()), ..TimingQuery::default() }), vec![every_field]);
+    }
+
+    /// The schema before timings recorded diagnostics
+    const V1_SCHEMA: &str = "
+        CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE unresolved_commits (position INTEGER PRIMARY KEY, id TEXT NOT NULL, note TEXT);
+        CREATE TABLE commits (id TEXT PRIMARY KEY, date TEXT NOT NULL, note TEXT, author TEXT,
+                              author_date TEXT, subject TEXT, parents TEXT NOT NULL, tags TEXT NOT NULL);
+        CREATE TABLE timings (id INTEGER PRIMARY KEY, commit_id TEXT NOT NULL, profile TEXT NOT NULL,
+                              rebuild_type TEXT NOT NULL, start TEXT NOT NULL, duration_ns INTEGER NOT NULL,
+                              result TEXT NOT NULL, toolchain TEXT, machine TEXT);
+        CREATE TABLE events (id INTEGER PRIMARY KEY, commit_id TEXT NOT NULL, event TEXT NOT NULL);
+        INSERT INTO meta (key, value) VALUES ('version', '1');
+        INSERT INTO commits VALUES ('67a32593bf76464ff650f7b116af403d7f4613c9', '2019-10-01T00:00:00.000000000Z',
+                                    NULL, NULL, NULL, NULL, '', '');
+        INSERT INTO timings VALUES (1, '67a32593bf76464ff650f7b116af403d7f4613c9', 'dev', 'full',
+                                    '2019-10-01T01:00:00.000000000Z', 1000000000, 'success', 'rustc 1.38.0', NULL);
+    ";
+
+    #[test]
+    fn upgrades_v1() {
+        let dir = TempDir::new("sqlite-v1");
+        let path = dir.join("maptime.sqlite");
+        Connection::open(&path).unwrap().execute_batch(V1_SCHEMA).unwrap();
+        let original = fs::read(&path).unwrap();
+
+        assert_eq!(version(&path).unwrap(), Some(1));
+        let report = check(&path).unwrap().unwrap();
+        assert_eq!((report.from, report.to, report.changes.len()), (1, VERSION, UPGRADES.len()));
+        assert_eq!(fs::read(&path).unwrap(), original);
+
+        let report = upgrade(&path).unwrap().unwrap();
+        assert_eq!(report.from, 1);
+        assert_eq!(version(&path).unwrap(), Some(VERSION));
+        assert_eq!(fs::read(migrate::backup_path(&path, 1)).unwrap(), original);
+        assert!(upgrade(&path).unwrap().is_none());
+
+        let data = SqliteStorage::open(&path, LockMode::Shared).unwrap().load().unwrap();
+        let timing = &data.timings[&id(A)][0];
+        assert_eq!(timing.duration, Duration::from_secs(1));
+        assert_eq!(timing.toolchain, Some("rustc 1.38.0".to_string()));
+        assert_eq!((timing.exit_code, &timing.error, &timing.log), (None, &None, &None));
+        assert_eq!((&timing.config, &timing.toolchain_name), (&None, &None));
+        assert!(data.configs.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+pub fn mean(samples: &[Duration]) -> Duration {
+    if samples.is_empty() {
+        return Duration::default();
+    }
+    let sum: Duration = samples.iter().sum();
+    sum / u32::try_from(samples.len()).expect("sample count should fit in u32")
+}
+
+pub fn median(samples: &[Duration]) -> Duration {
+    if samples.is_empty() {
+        return Duration::default();
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Sample standard deviation. Zero with fewer than two samples.
+pub fn stddev(samples: &[Duration]) -> Duration {
+    if samples.len() < 2 {
+        return Duration::default();
+    }
+    let mean = mean(samples).as_secs_f64();
+    let sum_sq: f64 = samples.iter().map(|s| (s.as_secs_f64() - mean).powi(2)).sum();
+    let var = sum_sq / (samples.len() - 1) as f64;
+    Duration::from_secs_f64(var.sqrt())
+}
+
+/// Welch's t statistic and its degrees of freedom, for samples with
+/// possibly different variances. `None` with fewer than two samples a side.
+pub fn welch_t(a: &[Duration], b: &[Duration]) -> Option<(f64, f64)> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let va = stddev(a).as_secs_f64().powi(2) / na;
+    let vb = stddev(b).as_secs_f64().powi(2) / nb;
+    let diff = mean(b).as_secs_f64() - mean(a).as_secs_f64();
+    if va + vb == 0.0 {
+        // No noise at all. Any difference is real.
+        let t = if diff == 0.0 { 0.0 } else { diff.signum() * std::f64::INFINITY };
+        return Some((t, na + nb - 2.0));
+    }
+    let t = diff / (va + vb).sqrt();
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    Some((t, df))
+}
+
+/// The two-tailed 95% critical value of Student's t distribution,
+/// rounded down to the nearest tabulated degrees of freedom
+pub fn t_critical_95(df: f64) -> f64 {
+    const TABLE: &[(f64, f64)] = &[
+        (1.0, 12.706), (2.0, 4.303), (3.0, 3.182), (4.0, 2.776), (5.0, 2.571),
+        (6.0, 2.447), (7.0, 2.365), (8.0, 2.306), (9.0, 2.262), (10.0, 2.228),
+        (15.0, 2.131), (20.0, 2.086), (30.0, 2.042), (60.0, 2.000), (120.0, 1.980),
+    ];
+    TABLE.iter().rev()
+        .find(|&&(d, _)| df >= d)
+        .map(|&(_, t)| t)
+        .unwrap_or(TABLE[0].1)
+}
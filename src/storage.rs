@@ -0,0 +1,204 @@
+//! Where the database lives.
+//!
+//! `.sqlite` and `.db` files are SQLite databases, queried by index and
+//! appended to without rewriting. Anything else is a JSON file rewritten
+//! whole on every change.
+
+use chrono::{DateTime, Utc};
+use std::error::Error as StdError;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use crate::commit_id::CommitId;
+use crate::data::{Data, Timing, VERSION};
+use crate::plot::SeriesKey;
+use crate::jsonfile::JsonStorage;
+use crate::lock::{self, Lock, LockMode};
+use crate::migrate::{self, Report};
+use crate::sqlite::{self, SqliteStorage};
+
+pub trait Storage {
+    /// Reads the whole database
+    fn load(&mut self) -> Result<Data, Error>;
+
+    /// Replaces the whole database with `data`
+    fn save(&mut self, data: &Data) -> Result<(), Error>;
+
+    /// Adds builds of one commit without rewriting the rest of the database
+    fn add_timings(&mut self, commit: &CommitId, timings: &[Timing]) -> Result<(), Error>;
+
+    /// Timings matching every condition of `query`, ordered by commit
+    /// date and then as recorded
+    fn query_timings(&mut self, query: &TimingQuery) -> Result<Vec<(CommitId, Timing)>, Error>;
+}
+
+/// Selects timings. Conditions left `None` match everything.
+#[derive(Debug, Default, Clone)]
+pub struct TimingQuery {
+    pub commit: Option<CommitId>,
+    pub series: Option<SeriesKey>,
+    /// Builds started at or after this
+    pub since: Option<DateTime<Utc>>,
+    /// Builds started before this
+    pub until: Option<DateTime<Utc>>,
+    pub machine: Option<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+/// An open database, locked until dropped
+pub struct Database {
+    storage: Box<dyn Storage>,
+    _lock: Lock,
+}
+
+/// Opens the database at `path`, creating it on first write. Older
+/// databases are migrated first.
+///
+/// Take `LockMode::Exclusive` to change the database, and hold it from
+/// loading to saving so no other process writes in between.
+pub fn open(path: &Path, mode: LockMode) -> Result<Database, Error> {
+    let backend = Backend::from_path(path);
+    let mut lock = lock::lock(path, mode)?;
+
+    // Most opens find nothing to migrate, and only read the version
+    if version(path)?.map_or(false, |version| version < VERSION) {
+        if mode == LockMode::Shared {
+            // Migrating writes, so wait for the other readers
+            drop(lock);
+            let exclusive = lock::lock(path, LockMode::Exclusive)?;
+            migrate_on_open(path)?;
+            drop(exclusive);
+            lock = lock::lock(path, LockMode::Shared)?;
+        } else {
+            migrate_on_open(path)?;
+        }
+    }
+
+    let storage: Box<dyn Storage> = match backend {
+        Backend::Json => Box::new(JsonStorage::open(path)?),
+        Backend::Sqlite => Box::new(SqliteStorage::open(path, mode)?),
+    };
+
+    Ok(Database {
+        storage,
+        _lock: lock,
+    })
+}
+
+/// The version of the database at `path`. `None` if it is missing
+pub fn version(path: &Path) -> Result<Option<u32>, Error> {
+    match Backend::from_path(path) {
+        Backend::Json => migrate::version(path).map_err(Error::Migrate),
+        Backend::Sqlite => sqlite::version(path),
+    }
+}
+
+/// Migrates as part of opening, noting the backup. Another process may
+/// have migrated the database since its version was read.
+fn migrate_on_open(path: &Path) -> Result<(), Error> {
+    if let Some(report) = upgrade(path)? {
+        eprintln!("migrated {} from version {} to {}. backup in {}",
+                  path.display(), report.from, report.to, migrate::backup_path(path, report.from).display());
+    }
+    Ok(())
+}
+
+/// Reports what migrating the database at `path` would change, without
+/// changing it. `None` if it is missing or already current.
+pub fn check(path: &Path) -> Result<Option<Report>, Error> {
+    match Backend::from_path(path) {
+        Backend::Json => migrate::check(path).map_err(Error::Migrate),
+        Backend::Sqlite => sqlite::check(path),
+    }
+}
+
+/// Migrates the database at `path` in place, first copying it to
+/// `migrate::backup_path`. Take an exclusive lock first. `None` if it is
+/// missing or already current.
+pub fn upgrade(path: &Path) -> Result<Option<Report>, Error> {
+    match Backend::from_path(path) {
+        Backend::Json => migrate::migrate_file(path).map_err(Error::Migrate),
+        Backend::Sqlite => sqlite::upgrade(path),
+    }
+}
+
+impl Deref for Database {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &(dyn Storage + 'static) {
+        &*self.storage
+    }
+}
+
+impl DerefMut for Database {
+    fn deref_mut(&mut self) -> &mut (dyn Storage + 'static) {
+        &mut *self.storage
+    }
+}
+
+impl Backend {
+    pub fn from_path(path: &Path) -> Backend {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sqlite") | Some("db") => Backend::Sqlite,
+            _ => Backend::Json,
+        }
+    }
+}
+
+impl TimingQuery {
+    pub fn matches(&self, commit: &CommitId, timing: &Timing) -> bool {
+        self.commit.as_ref().map_or(true, |c| c == commit)
+            && self.series.as_ref().map_or(true, |s| timing.is_in(s))
+            && self.since.map_or(true, |since| timing.start >= since)
+            && self.until.map_or(true, |until| timing.start < until)
+            && self.machine.as_ref().map_or(true, |m| timing.machine.as_ref() == Some(m))
+    }
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "loading blobject")]
+    AtomBlob(atomic_blobject::Error),
+    #[display(fmt = "migrating database")]
+    Migrate(migrate::Error),
+    #[display(fmt = "locking database")]
+    Lock(lock::Error),
+    #[display(fmt = "sqlite error")]
+    Sqlite(rusqlite::Error),
+    #[display(fmt = "bad {} in sqlite database", _0)]
+    Corrupt(&'static str),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::AtomBlob(ref e) => Some(e),
+            Error::Migrate(ref e) => Some(e),
+            Error::Lock(ref e) => Some(e),
+            Error::Sqlite(ref e) => Some(e),
+            Error::Corrupt(_) => None,
+        }
+    }
+}
+
+impl From<atomic_blobject::Error> for Error {
+    fn from(e: atomic_blobject::Error) -> Error {
+        Error::AtomBlob(e)
+    }
+}
+
+impl From<lock::Error> for Error {
+    fn from(e: lock::Error) -> Error {
+        Error::Lock(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Sqlite(e)
+    }
+}
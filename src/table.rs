@@ -0,0 +1,122 @@
+use crate::analysis::Comparison;
+use crate::plot::{PlotData, Entry};
+use crate::stats;
+use std::time::Duration;
+
+/// Rows of cells printed with aligned columns
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    /// Columns before this are left-aligned text, the rest right-aligned numbers
+    text_columns: usize,
+}
+
+impl Table {
+    pub fn new(header: Vec<String>, text_columns: usize) -> Table {
+        Table {
+            header,
+            rows: vec![],
+            text_columns,
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self) {
+        let mut widths: Vec<usize> = self.header.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        let print_row = |row: &[String]| {
+            let cells: Vec<String> = row.iter().zip(&widths).enumerate().map(|(i, (cell, &w))| {
+                if i < self.text_columns {
+                    format!("{:<w$}", cell, w = w)
+                } else {
+                    format!("{:>w$}", cell, w = w)
+                }
+            }).collect();
+            println!("{}", cells.join("  ").trim_end());
+        };
+
+        print_row(&self.header);
+        let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+        println!("{}", rule.join("  "));
+        for row in &self.rows {
+            print_row(row);
+        }
+    }
+}
+
+/// Prints each plotted commit with the median of every series and its
+/// change from the series' previous commit
+pub fn print_table(data: &PlotData) {
+    let mut header = vec!["commit".to_string(), "date".to_string(), "note".to_string()];
+    for series in &data.series {
+        header.push(series.caption());
+        header.push("delta".to_string());
+    }
+    let mut table = Table::new(header, 3);
+
+    for commit in data.commits() {
+        let mut row = vec![
+            commit.commit.short_id().to_string(),
+            data.x_axis.date(&commit.commit).format("%Y-%m-%d").to_string(),
+            commit.commit.note.clone().unwrap_or_default(),
+        ];
+        for series in &data.series {
+            let pos = series.values.iter().position(|e| e.index == commit.index);
+            match pos {
+                Some(pos) => {
+                    let secs = median(&series.values[pos]);
+                    row.push(format!("{:.2}s", secs));
+                    let delta = if pos > 0 {
+                        let prev = median(&series.values[pos - 1]);
+                        format!("{:+.1}%", (secs - prev) / prev * 100.0)
+                    } else {
+                        String::new()
+                    };
+                    row.push(delta);
+                }
+                None => {
+                    row.push("-".to_string());
+                    row.push(String::new());
+                }
+            }
+        }
+        table.push(row);
+    }
+
+    table.print();
+}
+
+/// Prints one row per configuration of two commits' medians and whether
+/// they differ significantly
+pub fn print_comparisons(comparisons: &[Comparison]) {
+    let header = ["series", "verdict", "before", "after", "diff", "change", "samples"];
+    let mut table = Table::new(header.iter().map(|h| h.to_string()).collect(), 2);
+    for c in comparisons {
+        let median = |samples: &[Duration], median: Duration| {
+            if samples.is_empty() { "-".to_string() } else { format!("{:.2}s", median.as_secs_f64()) }
+        };
+        let both = !c.before.is_empty() && !c.after.is_empty();
+        table.push(vec![
+            c.key.name(),
+            c.verdict().as_ref().to_string(),
+            median(&c.before, c.before_median()),
+            median(&c.after, c.after_median()),
+            if both { format!("{:+.2}s", c.diff()) } else { String::new() },
+            if both { format!("{:+.1}%", c.change() * 100.0) } else { String::new() },
+            format!("{}/{}", c.before.len(), c.after.len()),
+        ]);
+    }
+    table.print();
+}
+
+fn median(entry: &Entry) -> f64 {
+    stats::median(&entry.samples).as_secs_f64()
+}
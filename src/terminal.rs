@@ -0,0 +1,158 @@
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::io::{self, Write};
+use crate::plot::{PlotData, PlotOptions, YAxis};
+
+/// Point symbols, one per series
+const SYMBOLS: &[char] = &['*', '+', 'o', 'x', '#', '@', '%', '&'];
+
+/// Pixels per character cell, so `--width` and `--height` still apply
+const CELL: (u32, u32) = (8, 20);
+
+/// Draws the plot as text, for machines without a display
+pub fn render(data: &PlotData, opts: &PlotOptions, out: &mut dyn Write) -> Result<(), Error> {
+    let x_axis = data.x_axis;
+    let y_axis = data.y_axis;
+    let points: Vec<(i64, f64)> = data.series.iter()
+        .flat_map(|s| s.values.iter().map(move |e| (x_axis.x(e), s.y(e))))
+        .collect();
+
+    if points.is_empty() {
+        return Err(Error::NoData);
+    }
+
+    let cols = (opts.width / CELL.0).max(20) as usize;
+    let rows = (opts.height / CELL.1).max(5) as usize;
+
+    let mut x_min = points.iter().map(|p| p.0).min().expect("points");
+    let mut x_max = points.iter().map(|p| p.0).max().expect("points");
+    if x_min == x_max {
+        x_min -= 1;
+        x_max += 1;
+    }
+    let (y_min, y_max) = data.y_range(opts.log_scale);
+
+    let col = |x: i64| {
+        let frac = (x - x_min) as f64 / (x_max - x_min) as f64;
+        (frac * (cols - 1) as f64).round() as usize
+    };
+    let frac_y = |y: f64| {
+        if opts.log_scale {
+            (y.ln() - y_min.ln()) / (y_max.ln() - y_min.ln())
+        } else {
+            (y - y_min) / (y_max - y_min)
+        }
+    };
+    let row = |y: f64| -> Option<usize> {
+        let frac = frac_y(y);
+        if frac.is_finite() && frac >= 0.0 && frac <= 1.0 {
+            Some(rows - 1 - (frac * (rows - 1) as f64).round() as usize)
+        } else {
+            None
+        }
+    };
+    // The value shown at the start of a row, inverting `row`
+    let row_value = |r: usize| {
+        let frac = (rows - 1 - r) as f64 / (rows - 1) as f64;
+        if opts.log_scale {
+            (y_min.ln() + frac * (y_max.ln() - y_min.ln())).exp()
+        } else {
+            y_min + frac * (y_max - y_min)
+        }
+    };
+
+    let mut grid = vec![vec![' '; cols]; rows];
+
+    for marker in &data.markers {
+        let c = col(marker.x);
+        for line in grid.iter_mut() {
+            line[c] = '|';
+        }
+    }
+
+    if y_axis == YAxis::Percent {
+        if let Some(r) = row(100.0) {
+            for cell in grid[r].iter_mut().filter(|c| **c == ' ') {
+                *cell = '-';
+            }
+        }
+    }
+
+    for (i, series) in data.series.iter().enumerate() {
+        let symbol = SYMBOLS[i % SYMBOLS.len()];
+        for entry in &series.values {
+            if let Some(r) = row(series.y(entry)) {
+                grid[r][col(x_axis.x(entry))] = symbol;
+            }
+        }
+    }
+
+    let commits: BTreeMap<i64, &str> = data.commits().into_iter().map(|e| (e.index as i64, e.commit.short_id())).collect();
+    let x_label = |x: i64| {
+        if x_axis.is_time() {
+            Utc.timestamp(x, 0).format("%Y-%m-%d").to_string()
+        } else {
+            commits.get(&x).map(|id| id.to_string()).unwrap_or_default()
+        }
+    };
+
+    const MARGIN: usize = 10;
+
+    writeln!(out, "{:^width$}", opts.title, width = MARGIN + cols)?;
+    writeln!(out)?;
+    for (r, line) in grid.iter().enumerate() {
+        let label = if r == 0 || r == rows / 2 || r == rows - 1 {
+            format!("{:.1}{}", row_value(r), y_axis.unit())
+        } else {
+            String::new()
+        };
+        let line: String = line.iter().collect();
+        writeln!(out, "{:>w$} |{}", label, line.trim_end(), w = MARGIN - 2)?;
+    }
+    writeln!(out, "{:>w$}+{}", "", "-".repeat(cols), w = MARGIN - 1)?;
+
+    let (first, last) = (x_label(x_min), x_label(x_max));
+    let middle = x_label(x_min + (x_max - x_min) / 2);
+    let inner = cols.saturating_sub(first.len() + last.len());
+    writeln!(out, "{:w$}{}{:^inner$}{}", "", first, middle, last, w = MARGIN, inner = inner)?;
+    writeln!(out, "{:^width$}", x_axis.label(), width = MARGIN + cols)?;
+    writeln!(out)?;
+
+    for (i, series) in data.series.iter().enumerate() {
+        writeln!(out, "  {} {}", SYMBOLS[i % SYMBOLS.len()], series.caption())?;
+    }
+    if y_axis == YAxis::Percent {
+        writeln!(out, "  - 100%")?;
+    }
+    let mut markers: Vec<_> = data.markers.iter().collect();
+    markers.sort_by_key(|m| m.x);
+    for marker in markers {
+        writeln!(out, "  | {} {}: {}", x_label(marker.x), marker.kind.as_ref(), marker.label)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "no timings to plot")]
+    NoData,
+    #[display(fmt = "writing chart")]
+    Io(io::Error),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::NoData => None,
+            Error::Io(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by unit tests
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A directory of its own under the system temp directory, removed with
+/// everything in it when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` must be unique among tests, which run in parallel
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("maptime-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,312 @@
+//! Finds the toolchain that changed a fixed commit's build time, for
+//! compiler bug reports.
+//!
+//! Toolchains are ordered by the date of the rust-lang/rust commit they
+//! were built from, which stands in for a project commit on the plot.
+//! Nothing is recorded in the database.
+
+use std::error::Error as StdError;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::bisect;
+use crate::cargo;
+use crate::commit_id::CommitId;
+use crate::config::{Config, NamedConfig};
+use crate::data::{Commit, Data, Profile, Timing};
+use crate::git;
+use crate::opts::{GlobalOptions, Toolchain, ToolchainParseError};
+use crate::plot::{PlotData, SeriesKey, XAxis};
+use crate::stats;
+
+/// A toolchain and the rust-lang/rust commit it was built from
+#[derive(Debug, Clone)]
+pub struct DatedToolchain {
+    pub toolchain: Toolchain,
+    /// The compiler's commit, noted with the toolchain's name
+    pub commit: Commit,
+}
+
+/// The result of `bisect_toolchains`
+pub struct Bisection {
+    pub key: SeriesKey,
+    /// The last toolchain timed like the first of the range
+    pub old: DatedToolchain,
+    pub old_duration: Duration,
+    /// The first toolchain timed like the last of the range
+    pub new: DatedToolchain,
+    pub new_duration: Duration,
+    /// Every toolchain timed, in date order
+    pub plot: PlotData,
+}
+
+/// The toolchains to bisect: each one in `dir` with a `bin/rustc`, else
+/// those given with `--toolchain`, else every toolchain rustup has
+/// installed. Those without their own cargo use the one on the PATH.
+pub fn list_toolchains(opts: &GlobalOptions, dir: Option<&Path>) -> Result<Vec<Toolchain>, Error> {
+    if let Some(dir) = dir {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir).map_err(Error::Io)? {
+            let path = entry.map_err(Error::Io)?.path();
+            if path.join("bin").join("rustc").exists() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        return paths.iter()
+            .map(|p| Toolchain::from_str(&p.to_string_lossy()).map_err(Error::ToolchainName))
+            .collect();
+    }
+
+    if !opts.build.toolchains.is_empty() {
+        return Ok(opts.build.toolchains.clone());
+    }
+
+    let output = Command::new("rustup").args(&["toolchain", "list"]).output().map_err(Error::Rustup)?;
+    if !output.status.success() {
+        return Err(Error::RustupFailed);
+    }
+    // Lines like `stable-x86_64-unknown-linux-gnu (default)`
+    String::from_utf8_lossy(&output.stdout).lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|name| Toolchain::from_str(name).map_err(Error::ToolchainName))
+        .collect()
+}
+
+/// Times `toolchains` at `rev`, oldest compiler first. Times `sweep` of
+/// them evenly spaced, then bisects between the two with the biggest
+/// change in any of `series`, or any series if none are given.
+pub fn bisect_toolchains(opts: &GlobalOptions, rev: &str, toolchains: &[Toolchain], sweep: usize,
+                         series: &[SeriesKey]) -> Result<Bisection, Error> {
+    let repo = &opts.repo_path;
+    if git::is_dirty(repo)? {
+        return Err(Error::DirtyWorkTree);
+    }
+    let commit = git::read_commit_id(repo, rev)?;
+    let configs = Config::load(&opts.config)?.build_configs(&opts.build.configs)?;
+
+    let toolchains = date_toolchains(opts.project_path(), toolchains)?;
+    if toolchains.len() < 2 {
+        return Err(Error::NotEnoughToolchains);
+    }
+
+    let start_branch = git::current_branch(repo)?;
+    let start_commit = git::current_commit(repo)?;
+    eprintln!("checking out {}", commit.as_str());
+    git::checkout(repo, &commit)?;
+
+    let bisection = search(opts, toolchains, &configs, sweep, series);
+
+    git::restore(repo, start_branch.as_ref().map(String::as_str), &start_commit)?;
+
+    bisection
+}
+
+fn search(opts: &GlobalOptions, mut toolchains: Vec<DatedToolchain>, configs: &[NamedConfig], sweep: usize,
+          series: &[SeriesKey]) -> Result<Bisection, Error> {
+    let mut data = Data::default();
+
+    // Always includes the first and last
+    let count = toolchains.len();
+    let sweep = sweep.max(2).min(count);
+    for i in 0..sweep {
+        let toolchain = &toolchains[i * (count - 1) / (sweep - 1)];
+        eprintln!("sweep {} of {}: timing {}", i + 1, sweep, toolchain.toolchain.name);
+        for profile in [Profile::Dev, Profile::Release].iter().cloned() {
+            for config in configs {
+                time_build(opts, toolchain, profile, config, &mut data)?;
+            }
+        }
+    }
+
+    let mut plot = plot_data(&data, &toolchains);
+    plot.select(series);
+    // Unlike commits, neighboring toolchains still narrow the range
+    let range = bisect::find_biggest_range(plot, |_, _| Ok(false))?;
+    let key = range.key.clone();
+    let config = configs.iter().find(|c| c.name == key.config).expect("bisected configuration was built");
+    eprintln!("bisecting {} between {} and {}",
+              key.name(), range.first.commit.description().unwrap_or(""), range.last.commit.description().unwrap_or(""));
+
+    let position = |toolchains: &[DatedToolchain], id: &CommitId| {
+        toolchains.iter().position(|t| t.commit.id == *id).expect("plotted toolchain is listed")
+    };
+    let mut old = position(&toolchains, &range.first.commit.id);
+    let mut new = position(&toolchains, &range.last.commit.id);
+    let mut old_duration = range.first.duration;
+    let mut new_duration = range.last.duration;
+    while new - old > 1 {
+        let mid = (old + new) / 2;
+        eprintln!("{} toolchains left to bisect. timing {}", new - old - 1, toolchains[mid].toolchain.name);
+        time_build(opts, &toolchains[mid], key.profile, config, &mut data)?;
+
+        let samples = data.samples(&toolchains[mid].commit.id, &key);
+        if samples.is_empty() {
+            eprintln!("no {} build with {}. skipping it", key.name(), toolchains[mid].toolchain.name);
+            toolchains.remove(mid);
+            new -= 1;
+            continue;
+        }
+        let duration = stats::mean(&samples);
+        if range.is_new(duration) {
+            new = mid;
+            new_duration = duration;
+        } else {
+            old = mid;
+            old_duration = duration;
+        }
+    }
+
+    Ok(Bisection {
+        key,
+        old: toolchains[old].clone(),
+        old_duration,
+        new: toolchains[new].clone(),
+        new_duration,
+        plot: plot_data(&data, &toolchains),
+    })
+}
+
+/// Times one build with `toolchain`, recording it in `data` under the
+/// compiler's commit
+fn time_build(opts: &GlobalOptions, toolchain: &DatedToolchain, profile: Profile, config: &NamedConfig,
+              data: &mut Data) -> Result<(), Error> {
+    let project_path = opts.project_path();
+    let results = cargo::time_build(project_path, profile, Some(&toolchain.toolchain), config, &opts.build)?;
+
+    if let Some(touched) = results.touched {
+        // NB project_path, not repo_path
+        git::checkout_file(project_path, &touched)?;
+    }
+
+    let id = &toolchain.commit.id;
+    data.commits.entry(id.clone()).or_insert_with(|| toolchain.commit.clone());
+    let timings = data.timings.entry(id.clone()).or_insert(vec![]);
+    // The toolchain is the x-axis rather than a series
+    timings.extend(results.timings.into_iter().map(|t| Timing { toolchain_name: None, ..t }));
+    Ok(())
+}
+
+/// The timed toolchains, in date order
+fn plot_data(data: &Data, toolchains: &[DatedToolchain]) -> PlotData {
+    let commits: Vec<_> = toolchains.iter()
+        .map(|t| t.commit.id.clone())
+        .filter(|id| data.timings.contains_key(id))
+        .collect();
+    PlotData::from_data(data, &commits, XAxis::Index, false)
+}
+
+/// Reads each toolchain's commit from `rustc -vV` and sorts them by its
+/// date. Toolchains built from the same commit are timed once, and those
+/// without a commit aren't timed.
+fn date_toolchains(path: &Path, toolchains: &[Toolchain]) -> Result<Vec<DatedToolchain>, Error> {
+    let mut dated: Vec<DatedToolchain> = vec![];
+    for toolchain in toolchains {
+        let version = cargo::rustc_verbose_version(path, Some(toolchain))?;
+        let field = |name: &str| {
+            version.lines().find(|l| l.starts_with(name)).map(|l| l[name.len()..].trim())
+        };
+        let id = field("commit-hash:").and_then(|h| CommitId::from_str(h).ok());
+        let date = field("commit-date:").and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+        let (id, date) = match (id, date) {
+            (Some(id), Some(date)) => (id, date),
+            _ => {
+                // Like a local build with `ignore-git`, which reports `unknown`
+                eprintln!("{} has no commit hash and date in `rustc -vV`. skipping it", toolchain.name);
+                continue;
+            }
+        };
+
+        if let Some(same) = dated.iter().find(|t| t.commit.id == id) {
+            eprintln!("{} is the same compiler as {}. skipping it", toolchain.name, same.toolchain.name);
+            continue;
+        }
+        eprintln!("{} was built from rust-lang/rust commit {} of {}", toolchain.name, id.as_str(), date);
+        dated.push(DatedToolchain {
+            toolchain: toolchain.clone(),
+            commit: Commit {
+                id,
+                date: DateTime::from_utc(date.and_hms(0, 0, 0), Utc),
+                note: Some(toolchain.name.clone()),
+                author: None,
+                author_date: None,
+                subject: version.lines().next().map(str::to_string),
+                parents: vec![],
+                tags: vec![],
+            },
+        });
+    }
+
+    // Stable, so toolchains from the same day keep their listed order
+    dated.sort_by_key(|t| t.commit.date);
+    Ok(dated)
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display(fmt = "git error")]
+    Git(crate::git::Error),
+    #[display(fmt = "running cargo")]
+    Cargo(crate::cargo::Error),
+    #[display(fmt = "loading config")]
+    Config(crate::config::Error),
+    #[display(fmt = "finding the biggest change")]
+    Bisect(crate::bisect::Error),
+    #[display(fmt = "reading toolchain directory")]
+    Io(io::Error),
+    #[display(fmt = "running `rustup toolchain list`")]
+    Rustup(io::Error),
+    #[display(fmt = "`rustup toolchain list` failed")]
+    RustupFailed,
+    #[display(fmt = "naming toolchain")]
+    ToolchainName(ToolchainParseError),
+    #[display(fmt = "need at least two toolchains built from different, known commits")]
+    NotEnoughToolchains,
+    #[display(fmt = "working tree has uncommitted changes. commit or stash them first")]
+    DirtyWorkTree,
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Git(ref e) => Some(e),
+            Error::Cargo(ref e) => Some(e),
+            Error::Config(ref e) => Some(e),
+            Error::Bisect(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::Rustup(ref e) => Some(e),
+            Error::RustupFailed => None,
+            Error::ToolchainName(ref e) => Some(e),
+            Error::NotEnoughToolchains => None,
+            Error::DirtyWorkTree => None,
+        }
+    }
+}
+
+impl From<crate::git::Error> for Error {
+    fn from(e: crate::git::Error) -> Error {
+        Error::Git(e)
+    }
+}
+
+impl From<crate::cargo::Error> for Error {
+    fn from(e: crate::cargo::Error) -> Error {
+        Error::Cargo(e)
+    }
+}
+
+impl From<crate::config::Error> for Error {
+    fn from(e: crate::config::Error) -> Error {
+        Error::Config(e)
+    }
+}
+
+impl From<crate::bisect::Error> for Error {
+    fn from(e: crate::bisect::Error) -> Error {
+        Error::Bisect(e)
+    }
+}
@@ -0,0 +1,181 @@
+use std::error::Error as StdError;
+use std::path::Path;
+use crate::analysis::{self, Comparison};
+use crate::cargo;
+use crate::commit_id::CommitId;
+use crate::config::Config;
+use crate::data::{Data, Profile};
+use crate::git;
+use crate::opts::GlobalOptions;
+
+/// What `maptime try` times against the base
+#[derive(Debug, Clone, Copy)]
+pub enum Candidate<'a> {
+    /// Uncommitted changes to tracked files
+    WorkingTree,
+    /// A patch applied to the base
+    Patch(&'a Path),
+    /// A branch, tag or commit
+    Rev(&'a str),
+}
+
+/// Times `candidate` against `base`, alternating between the two, and
+/// compares them. Nothing is recorded in the database.
+pub fn try_changes(opts: &GlobalOptions, base: &str, candidate: Candidate, runs: u32) -> Result<Vec<Comparison>, Error> {
+    let repo = &opts.repo_path;
+    let base = git::read_commit_id(repo, base)?;
+    let start_branch = git::current_branch(repo)?;
+    let start_commit = git::current_commit(repo)?;
+
+    let mut stashed = false;
+    let candidate = match candidate {
+        Candidate::WorkingTree => {
+            let id = git::stash_create(repo)?.ok_or(Error::NoChanges)?;
+            git::stash_store(repo, &id, "maptime try")?;
+            git::reset_hard(repo)?;
+            stashed = true;
+            eprintln!("saved working tree changes on the stash as {}", id.as_str());
+            id
+        }
+        Candidate::Patch(patch) => {
+            if git::is_dirty(repo)? {
+                return Err(Error::DirtyWorkTree);
+            }
+            git::checkout(repo, &base)?;
+            let id = git::apply(repo, patch).and_then(|_| git::stash_create(repo));
+            // Discards the patch whether or not it was saved
+            git::reset_hard(repo)?;
+            let start_branch = start_branch.as_ref().map(String::as_str);
+            match id {
+                Ok(Some(id)) => id,
+                Ok(None) => {
+                    git::restore(repo, start_branch, &start_commit)?;
+                    return Err(Error::EmptyPatch);
+                }
+                Err(e) => {
+                    git::restore(repo, start_branch, &start_commit)?;
+                    return Err(Error::Git(e));
+                }
+            }
+        }
+        Candidate::Rev(rev) => {
+            if git::is_dirty(repo)? {
+                return Err(Error::DirtyWorkTree);
+            }
+            git::read_commit_id(repo, rev)?
+        }
+    };
+
+    let timings = time_alternating(opts, &[&base, &candidate], runs);
+
+    git::restore(repo, start_branch.as_ref().map(String::as_str), &start_commit)?;
+    if stashed {
+        eprintln!("restoring working tree changes");
+        git::stash_pop(repo)?;
+    }
+
+    let timings = timings?;
+    Ok(analysis::compare(&timings, &base, &candidate))
+}
+
+/// Times a committed revision without recording it. Returns its ID and
+/// the timings in a scratch `Data`.
+pub fn time_rev(opts: &GlobalOptions, rev: &str, runs: u32) -> Result<(CommitId, Data), Error> {
+    let repo = &opts.repo_path;
+    if git::is_dirty(repo)? {
+        return Err(Error::DirtyWorkTree);
+    }
+    let commit = git::read_commit_id(repo, rev)?;
+    let start_branch = git::current_branch(repo)?;
+    let start_commit = git::current_commit(repo)?;
+
+    let timings = time_alternating(opts, &[&commit], runs);
+
+    git::restore(repo, start_branch.as_ref().map(String::as_str), &start_commit)?;
+
+    Ok((commit, timings?))
+}
+
+/// Collects timings in a scratch `Data`. Runs reverse order each time so
+/// drift over the session affects every commit equally.
+fn time_alternating(opts: &GlobalOptions, commits: &[&CommitId], runs: u32) -> Result<Data, Error> {
+    let mut data = Data::default();
+    let project_path = opts.project_path();
+    let configs = Config::load(&opts.config)?.build_configs(&opts.build.configs)?;
+
+    for run in 0..runs {
+        let mut order = commits.to_vec();
+        if run % 2 == 1 {
+            order.reverse();
+        }
+        for commit in order {
+            eprintln!("run {} of {}: timing {}", run + 1, runs, commit.as_str());
+            git::checkout(&opts.repo_path, commit)?;
+
+            for profile in [Profile::Dev, Profile::Release].iter().cloned() {
+                for config in &configs {
+                    for toolchain in opts.build.toolchains() {
+                        let results = cargo::time_build(project_path, profile, toolchain, config, &opts.build)?;
+
+                        let timings = data.timings.entry(commit.clone()).or_insert(vec![]);
+                        timings.extend(results.timings);
+
+                        if let Some(touched) = results.touched {
+                            // NB project_path, not repo_path
+                            git::checkout_file(project_path, &touched)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+#[derive(Display, Debug)]
+pub enum Error {
+    #[display(fmt = "git error")]
+    Git(crate::git::Error),
+    #[display(fmt = "running cargo")]
+    Cargo(crate::cargo::Error),
+    #[display(fmt = "loading config")]
+    Config(crate::config::Error),
+    #[display(fmt = "working tree has uncommitted changes. commit or stash them first")]
+    DirtyWorkTree,
+    #[display(fmt = "working tree has no changes to try")]
+    NoChanges,
+    #[display(fmt = "patch makes no changes")]
+    EmptyPatch,
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Git(ref e) => Some(e),
+            Error::Cargo(ref e) => Some(e),
+            Error::Config(ref e) => Some(e),
+            Error::DirtyWorkTree => None,
+            Error::NoChanges => None,
+            Error::EmptyPatch => None,
+        }
+    }
+}
+
+impl From<crate::git::Error> for Error {
+    fn from(e: crate::git::Error) -> Error {
+        Error::Git(e)
+    }
+}
+
+impl From<crate::cargo::Error> for Error {
+    fn from(e: crate::cargo::Error) -> Error {
+        Error::Cargo(e)
+    }
+}
+
+impl From<crate::config::Error> for Error {
+    fn from(e: crate::config::Error) -> Error {
+        Error::Config(e)
+    }
+}